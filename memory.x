@@ -6,8 +6,10 @@
 MEMORY{
     BOOT2 : ORIGIN = 0x10000000,
     LENGTH = 0x100
+    /* The last 8K of the flash hold the saved settings, and the space
+       between the end of the image and them the data log (see src/flash.rs) */
     FLASH : ORIGIN = 0x10000100,
-    LENGTH = 2048K - 0x100
+    LENGTH = 2048K - 0x100 - 8K
              RAM : ORIGIN = 0x20000000,
     LENGTH = 256K
 }
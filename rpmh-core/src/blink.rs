@@ -0,0 +1,264 @@
+use core::fmt;
+
+use crate::dht;
+use crate::time::{Duration, Instant};
+
+// Fault conditions reported by blinking an LED. Each code is shown as a
+// number of short blinks (SensorNack = 1 ... WatchdogReset = 5) followed by a
+// pause, so the faults can be told apart in the field without the LCD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultCode {
+    // The sensor stopped answering (bus error, timeout or lost calibration)
+    SensorNack,
+    // A measurement frame arrived with a bad CRC
+    CrcFailure,
+    // The LCD did not acknowledge a write
+    LcdNack,
+    // The sensor returned a reading outside its rated range
+    OutOfRange,
+    // The last reset was caused by the watchdog
+    WatchdogReset,
+}
+
+impl FaultCode {
+    pub const ALL: [FaultCode; 5] = [
+        FaultCode::SensorNack,
+        FaultCode::CrcFailure,
+        FaultCode::LcdNack,
+        FaultCode::OutOfRange,
+        FaultCode::WatchdogReset,
+    ];
+
+    // Number of blinks in the code (1-5)
+    pub fn blinks(self) -> u8 {
+        self.index() as u8 + 1
+    }
+
+    // Position of the code in ALL, e.g. to pick an LED of the bar graph
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    // Map a sensor read error to the code that reports it. ReadTooFast is a
+    // timing slip in the caller rather than a fault, so it has no code.
+    pub fn from_sensor_error<E: fmt::Debug>(error: &dht::Error<E>) -> Option<FaultCode> {
+        match error {
+            dht::Error::I2cError(_) | dht::Error::Timeout | dht::Error::NotCalibrated => {
+                Some(FaultCode::SensorNack)
+            }
+            dht::Error::CrcMismatch => Some(FaultCode::CrcFailure),
+            dht::Error::ReadTooFast => None,
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self.index()
+    }
+}
+
+// Timing of one code: BLINK_ON lit and BLINK_OFF dark per blink, then
+// CODE_GAP dark before the pattern repeats (or the next fault is shown)
+pub const BLINK_ON: Duration = Duration::from_millis(200);
+pub const BLINK_OFF: Duration = Duration::from_millis(300);
+pub const CODE_GAP: Duration = Duration::from_millis(1500);
+
+// Whether the LED is lit `elapsed` into one showing of `code`
+fn lit_at(code: FaultCode, elapsed: Duration) -> bool {
+    let period = (BLINK_ON + BLINK_OFF).as_micros();
+    let blink = elapsed.as_micros() / period;
+    blink < code.blinks() as u64 && elapsed.as_micros() % period < BLINK_ON.as_micros()
+}
+
+// Length of one showing of `code`, including the gap after it
+fn pattern_length(code: FaultCode) -> Duration {
+    Duration::from_micros((BLINK_ON + BLINK_OFF).as_micros() * code.blinks() as u64) + CODE_GAP
+}
+
+// What a blinking LED should show right now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Blink {
+    pub code: FaultCode,
+    pub lit: bool,
+}
+
+// Non-blocking blink code engine. Faults are raised and cleared as they come
+// and go, and update() is called from the main loop (every 10-50 ms) to find
+// out whether the LED should be lit. With several faults active, each one's
+// code is shown in turn.
+#[derive(Debug, Clone, Default)]
+pub struct Blinker {
+    // one bit per FaultCode
+    faults: u8,
+    // the code being shown and when its current showing started
+    showing: Option<(FaultCode, Instant)>,
+}
+
+impl Blinker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn raise(&mut self, code: FaultCode) {
+        self.faults |= code.bit();
+    }
+
+    pub fn clear(&mut self, code: FaultCode) {
+        self.faults &= !code.bit();
+    }
+
+    // Raise or clear a fault depending on `active`
+    pub fn set(&mut self, code: FaultCode, active: bool) {
+        if active {
+            self.raise(code);
+        } else {
+            self.clear(code);
+        }
+    }
+
+    pub fn is_raised(&self, code: FaultCode) -> bool {
+        self.faults & code.bit() != 0
+    }
+
+    pub fn any_raised(&self) -> bool {
+        self.faults != 0
+    }
+
+    // The raised faults as a bit set, bit n for FaultCode::ALL[n]
+    pub fn raised(&self) -> u8 {
+        self.faults
+    }
+
+    // Advance the pattern to `now`. Returns None when there are no faults to
+    // show (the LED should be off).
+    pub fn update(&mut self, now: Instant) -> Option<Blink> {
+        let (mut code, mut started) = match self.showing {
+            Some((code, started)) if self.is_raised(code) => (code, started),
+            // start the next fault straight away if the shown one was cleared
+            _ => (self.next_after(self.showing.map(|(code, _)| code))?, now),
+        };
+
+        // move on to the next raised fault after each full showing
+        while now.duration_since(started) >= pattern_length(code) {
+            started = started + pattern_length(code);
+            code = self.next_after(Some(code))?;
+        }
+
+        self.showing = Some((code, started));
+        Some(Blink {
+            code,
+            lit: lit_at(code, now.duration_since(started)),
+        })
+    }
+
+    // The next raised fault after `code` in ALL order, wrapping around
+    fn next_after(&self, code: Option<FaultCode>) -> Option<FaultCode> {
+        let start = code.map_or(0, |code| code.index() + 1);
+        (0..FaultCode::ALL.len())
+            .map(|offset| FaultCode::ALL[(start + offset) % FaultCode::ALL.len()])
+            .find(|code| self.is_raised(*code))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(ms: u64) -> Instant {
+        Instant::from_micros(ms * 1000)
+    }
+
+    // Sample the LED every 100 ms from `from_ms` to `to_ms` (exclusive)
+    fn samples(blinker: &mut Blinker, from_ms: u64, to_ms: u64) -> Vec<bool> {
+        (from_ms..to_ms)
+            .step_by(100)
+            .map(|ms| blinker.update(at(ms)).is_some_and(|blink| blink.lit))
+            .collect()
+    }
+
+    #[test]
+    fn no_faults_keeps_led_off() {
+        let mut blinker = Blinker::new();
+        assert_eq!(blinker.update(at(0)), None);
+        assert!(!blinker.any_raised());
+    }
+
+    #[test]
+    fn codes_have_distinct_blink_counts() {
+        for (i, code) in FaultCode::ALL.iter().enumerate() {
+            assert_eq!(code.blinks() as usize, i + 1);
+        }
+    }
+
+    #[test]
+    fn crc_failure_blinks_twice_then_pauses() {
+        let mut blinker = Blinker::new();
+        blinker.raise(FaultCode::CrcFailure);
+
+        // two 200 ms blinks 500 ms apart, then dark until the 2.5 s pattern repeats
+        let expected = [
+            true, true, false, false, false, true, true, false, false, false, // 0-1 s
+            false, false, false, false, false, false, false, false, false, false, // 1-2 s
+            false, false, false, false, false, true, true, false, // 2-2.8 s
+        ];
+        assert_eq!(samples(&mut blinker, 0, 2800), expected);
+    }
+
+    #[test]
+    fn several_faults_take_turns() {
+        let mut blinker = Blinker::new();
+        blinker.raise(FaultCode::SensorNack);
+        blinker.raise(FaultCode::LcdNack);
+
+        assert_eq!(blinker.update(at(0)).unwrap().code, FaultCode::SensorNack);
+        // the sensor code takes 2 s, then the LCD code (3 s) follows
+        assert_eq!(blinker.update(at(2000)).unwrap().code, FaultCode::LcdNack);
+        assert_eq!(blinker.update(at(4900)).unwrap().code, FaultCode::LcdNack);
+        assert_eq!(blinker.update(at(5000)).unwrap().code, FaultCode::SensorNack);
+    }
+
+    #[test]
+    fn clearing_the_shown_fault_moves_on() {
+        let mut blinker = Blinker::new();
+        blinker.raise(FaultCode::SensorNack);
+        blinker.raise(FaultCode::WatchdogReset);
+        assert_eq!(blinker.update(at(0)).unwrap().code, FaultCode::SensorNack);
+
+        blinker.clear(FaultCode::SensorNack);
+        let blink = blinker.update(at(700)).unwrap();
+        assert_eq!(blink.code, FaultCode::WatchdogReset);
+        // the new code starts from its first blink
+        assert!(blink.lit);
+
+        blinker.clear(FaultCode::WatchdogReset);
+        assert_eq!(blinker.update(at(800)), None);
+    }
+
+    #[test]
+    fn late_updates_stay_in_step() {
+        let mut blinker = Blinker::new();
+        blinker.raise(FaultCode::SensorNack);
+        blinker.update(at(0));
+        // ten 2 s patterns later, 100 ms into the blink
+        assert_eq!(
+            blinker.update(at(20_100)),
+            Some(Blink { code: FaultCode::SensorNack, lit: true })
+        );
+    }
+
+    #[test]
+    fn sensor_errors_map_to_codes() {
+        assert_eq!(
+            FaultCode::from_sensor_error(&dht::Error::I2cError(())),
+            Some(FaultCode::SensorNack)
+        );
+        assert_eq!(
+            FaultCode::from_sensor_error::<()>(&dht::Error::Timeout),
+            Some(FaultCode::SensorNack)
+        );
+        assert_eq!(
+            FaultCode::from_sensor_error::<()>(&dht::Error::CrcMismatch),
+            Some(FaultCode::CrcFailure)
+        );
+        assert_eq!(FaultCode::from_sensor_error::<()>(&dht::Error::ReadTooFast), None);
+    }
+}
@@ -0,0 +1,663 @@
+use core::fmt::{self, Write};
+
+use embedded_storage::nor_flash::MultiwriteNorFlash;
+use heapless::String;
+
+use crate::blink::FaultCode;
+use crate::dht::Reading;
+use crate::settings_store::SECTOR_SIZE;
+use crate::time::Instant;
+use crate::utils::crc32;
+
+// A history of readings kept in flash, so they can be collected later over
+// the serial console even if nobody was connected when they were taken.
+//
+// The log is a ring of whole sectors. Entries are only ever appended: each
+// one is programmed into the next blank 16 bytes of the newest (head)
+// sector, and once that is full the oldest sector is erased and becomes the
+// head. So each sector is erased once per trip around the ring, and a power
+// cut can at worst tear the entry or sector header being written, which
+// fails its CRC and is skipped.
+//
+// There is no real-time clock, so entries are stamped with the number of
+// the boot they were taken in and the time since that boot.
+
+// Each sector starts with a header, then holds ENTRIES_PER_SECTOR entries
+const ENTRY_LEN: usize = 16;
+const SLOTS_PER_SECTOR: u32 = SECTOR_SIZE / ENTRY_LEN as u32;
+pub const ENTRIES_PER_SECTOR: u32 = SLOTS_PER_SECTOR - 1;
+// Entries are programmed a flash page at a time, with the rest of the page
+// left as it was (the flash must allow programming a page more than once)
+const PAGE_SIZE: usize = 256;
+
+// Sector header layout:
+//   0..4    "RLOG"
+//   4..6    log version, little-endian
+//   6..8    reserved, 0
+//   8..12   sector sequence number, one more than the previous head's
+//   12..16  CRC-32 of all the above
+const MAGIC: [u8; 4] = *b"RLOG";
+const VERSION: u16 = 1;
+
+// Entry layout, all little-endian:
+//   0..4    seconds since boot
+//   4..6    boot number
+//   6..8    humidity in hundredths of %RH, NO_HUMIDITY if the read failed
+//   8..10   temperature in hundredths of a degree C, NO_TEMPERATURE likewise
+//   10      raised fault codes, bit n for FaultCode::ALL[n]
+//   11      reserved, 0 (so an entry is never all 0xFF, i.e. blank)
+//   12..16  CRC-32 of all the above
+const NO_HUMIDITY: u16 = u16::MAX;
+const NO_TEMPERATURE: i16 = i16::MIN;
+
+pub const CSV_HEADER: &str = "boot,uptime_s,temperature_c,humidity_rh,faults\r\n";
+// Longest line an entry makes (every fault raised)
+const MAX_CSV_LINE: usize = 96;
+
+// One logged reading
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    // Counts up from 0 at each reset (wrapping), to tell the boots apart
+    pub boot: u16,
+    pub uptime_secs: u32,
+    // None if the sensor couldn't be read. Kept to 0.01 C and 0.01 %RH.
+    pub reading: Option<Reading>,
+    // Bit n set if FaultCode::ALL[n] was raised (see Blinker::raised)
+    pub faults: u8,
+}
+
+impl Entry {
+    fn to_bytes(&self) -> [u8; ENTRY_LEN] {
+        let (hum, temp) = match &self.reading {
+            Some(reading) => (
+                libm::roundf(reading.hum * 100.0) as u16,
+                libm::roundf(reading.temp * 100.0) as i16,
+            ),
+            None => (NO_HUMIDITY, NO_TEMPERATURE),
+        };
+        let mut bytes = [0; ENTRY_LEN];
+        bytes[0..4].copy_from_slice(&self.uptime_secs.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.boot.to_le_bytes());
+        bytes[6..8].copy_from_slice(&hum.to_le_bytes());
+        bytes[8..10].copy_from_slice(&temp.to_le_bytes());
+        bytes[10] = self.faults;
+        let crc = crc32(&bytes[..12]);
+        bytes[12..].copy_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    // None unless the CRC matches
+    fn from_bytes(bytes: &[u8; ENTRY_LEN]) -> Option<Entry> {
+        if u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]) != crc32(&bytes[..12]) {
+            return None;
+        }
+        let hum = u16::from_le_bytes([bytes[6], bytes[7]]);
+        let temp = i16::from_le_bytes([bytes[8], bytes[9]]);
+        let reading = (hum != NO_HUMIDITY && temp != NO_TEMPERATURE).then(|| Reading {
+            temp: temp as f32 / 100.0,
+            hum: hum as f32 / 100.0,
+        });
+        Some(Entry {
+            boot: u16::from_le_bytes([bytes[4], bytes[5]]),
+            uptime_secs: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            reading,
+            faults: bytes[10],
+        })
+    }
+
+    // e.g. "3,3600,23.44,45.16,"
+    //   or "3,3610,,,SensorNack LcdNack"
+    pub fn write_csv<W: Write>(&self, out: &mut W) -> fmt::Result {
+        write!(out, "{},{},", self.boot, self.uptime_secs)?;
+        if let Some(reading) = &self.reading {
+            write_hundredths(out, libm::roundf(reading.temp * 100.0) as i32)?;
+            out.write_char(',')?;
+            write_hundredths(out, libm::roundf(reading.hum * 100.0) as i32)?;
+        } else {
+            out.write_char(',')?;
+        }
+        out.write_char(',')?;
+        let raised = FaultCode::ALL.into_iter().filter(|code| self.faults & (1 << code.index()) != 0);
+        for (i, code) in raised.enumerate() {
+            if i > 0 {
+                out.write_char(' ')?;
+            }
+            write!(out, "{:?}", code)?;
+        }
+        out.write_str("\r\n")
+    }
+}
+
+// e.g. -505 as "-5.05"
+fn write_hundredths<W: Write>(out: &mut W, value: i32) -> fmt::Result {
+    let sign = if value < 0 { "-" } else { "" };
+    let abs = value.unsigned_abs();
+    write!(out, "{}{}.{:02}", sign, abs / 100, abs % 100)
+}
+
+// Where a dump has got to: the next slot to read, counting sectors from the
+// oldest
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor {
+    sectors_left: u32,
+    sector: u32,
+    slot: u32,
+}
+
+// The log written out as CSV, a bit at a time: send() is called from each
+// pass of the main loop and hands over only what the serial port has room
+// for, so a long log neither holds up the loop nor gets dropped when the
+// port's buffer fills.
+pub struct Dump {
+    cursor: Cursor,
+    // The line being sent, and how much of it has gone
+    line: String<MAX_CSV_LINE>,
+    sent: usize,
+}
+
+impl Dump {
+    // The CSV header, then each entry, oldest first
+    pub fn new<F: MultiwriteNorFlash>(log: &DataLog<F>) -> Self {
+        let mut line = String::new();
+        let _ = line.push_str(CSV_HEADER);
+        Dump {
+            cursor: log.oldest(),
+            line,
+            sent: 0,
+        }
+    }
+
+    // Pass on as much of the dump as `write` takes (it returns how many of
+    // the bytes it was given it took, 0 once it is full). Returns false when
+    // everything has been sent.
+    pub fn send<F, W>(&mut self, log: &mut DataLog<F>, mut write: W) -> Result<bool, F::Error>
+    where
+        F: MultiwriteNorFlash,
+        W: FnMut(&[u8]) -> usize,
+    {
+        loop {
+            if self.sent == self.line.len() {
+                self.line.clear();
+                self.sent = 0;
+                match log.read_next(&mut self.cursor)? {
+                    Some(entry) => {
+                        let _ = entry.write_csv(&mut self.line);
+                    }
+                    None => return Ok(false),
+                }
+            }
+            match write(&self.line.as_bytes()[self.sent..]) {
+                0 => return Ok(true),
+                written => self.sent += written,
+            }
+        }
+    }
+}
+
+// Why a log couldn't be opened
+#[derive(Debug, PartialEq)]
+pub enum OpenError<E> {
+    // The region isn't whole sectors, or has fewer than two (e.g. a firmware
+    // image so large it runs into the settings)
+    BadRegion,
+    // The flash couldn't be read, or the first sector erased
+    Flash(E),
+}
+
+impl<E> OpenError<E> {
+    // Short description, e.g. for the status command
+    pub fn description(&self) -> &'static str {
+        match self {
+            OpenError::BadRegion => "no room in flash",
+            OpenError::Flash(_) => "flash error",
+        }
+    }
+}
+
+pub struct DataLog<F> {
+    flash: F,
+    // Offset of the first sector of the log, and how many it has
+    start: u32,
+    sectors: u32,
+    // The head sector, its sequence number and the next slot to fill
+    // (SLOTS_PER_SECTOR once it is full)
+    head: u32,
+    sequence: u32,
+    next: u32,
+    // Number of this boot, stamped on each entry
+    boot: u16,
+}
+
+impl<F: MultiwriteNorFlash> DataLog<F> {
+    // Take over the sectors in start..end (both sector aligned, at least two
+    // sectors apart), carrying on from the log already there. Flash with no
+    // log in it is started afresh.
+    pub fn open(flash: F, start: u32, end: u32) -> Result<Self, OpenError<F::Error>> {
+        let aligned = start.is_multiple_of(SECTOR_SIZE) && end.is_multiple_of(SECTOR_SIZE);
+        if !aligned || end < start.saturating_add(2 * SECTOR_SIZE) {
+            return Err(OpenError::BadRegion);
+        }
+        let mut log = DataLog {
+            flash,
+            start,
+            sectors: (end - start) / SECTOR_SIZE,
+            head: 0,
+            sequence: 0,
+            next: 1,
+            boot: 0,
+        };
+        log.carry_on().map_err(OpenError::Flash)?;
+        Ok(log)
+    }
+
+    // Find where the log in flash left off: the head sector, the next slot
+    // in it and the number of this boot
+    fn carry_on(&mut self) -> Result<(), F::Error> {
+        // the head is the sector with the newest intact header
+        let mut newest: Option<(u32, u32)> = None;
+        for sector in 0..self.sectors {
+            if let Some(sequence) = self.read_header(sector)? {
+                if newest.is_none_or(|(_, newest_sequence)| sequence > newest_sequence) {
+                    newest = Some((sector, sequence));
+                }
+            }
+        }
+        let Some((head, sequence)) = newest else {
+            self.start_sector(0, 0)?;
+            return Ok(());
+        };
+        self.head = head;
+        self.sequence = sequence;
+
+        // filled up to just after the last slot written, even if that entry
+        // was torn
+        self.next = 1;
+        let mut last = None;
+        for slot in 1..SLOTS_PER_SECTOR {
+            let bytes = self.read_slot(head, slot)?;
+            if bytes.iter().any(|byte| *byte != 0xFF) {
+                self.next = slot + 1;
+                last = Entry::from_bytes(&bytes).or(last);
+            }
+        }
+        // the boot before this one logged last, in the head or (if the head
+        // was only just started) the sector before it
+        if last.is_none() {
+            let previous = (head + self.sectors - 1) % self.sectors;
+            if self.read_header(previous)? == Some(sequence.wrapping_sub(1)) {
+                for slot in 1..SLOTS_PER_SECTOR {
+                    last = Entry::from_bytes(&self.read_slot(previous, slot)?).or(last);
+                }
+            }
+        }
+        self.boot = last.map_or(0, |entry| entry.boot.wrapping_add(1));
+        Ok(())
+    }
+
+    // Number of this boot, as stamped on its entries
+    pub fn boot(&self) -> u16 {
+        self.boot
+    }
+
+    // Entries the log holds once it has gone all the way round (the oldest
+    // sector is erased to make room, so one sector's worth fewer than fit)
+    pub fn capacity(&self) -> u32 {
+        (self.sectors - 1) * ENTRIES_PER_SECTOR
+    }
+
+    // Log a reading (None if the sensor couldn't be read) taken at
+    // `timestamp`, with the fault codes raised at the time
+    pub fn append(&mut self, timestamp: Instant, reading: Option<&Reading>, faults: u8) -> Result<(), F::Error> {
+        if self.next == SLOTS_PER_SECTOR {
+            let head = (self.head + 1) % self.sectors;
+            self.start_sector(head, self.sequence.wrapping_add(1))?;
+        }
+        let entry = Entry {
+            boot: self.boot,
+            uptime_secs: (timestamp.as_micros() / 1_000_000) as u32,
+            reading: reading.cloned(),
+            faults,
+        };
+        self.program(self.head, self.next, &entry.to_bytes())?;
+        self.next += 1;
+        Ok(())
+    }
+
+    // A cursor at the oldest entry, for reading the log through with
+    // read_next
+    pub fn oldest(&self) -> Cursor {
+        Cursor {
+            sectors_left: self.sectors,
+            sector: (self.head + 1) % self.sectors,
+            slot: 0,
+        }
+    }
+
+    // The entry at the cursor, moving it on, or None once the newest entry
+    // has been read. Damaged entries are skipped.
+    pub fn read_next(&mut self, cursor: &mut Cursor) -> Result<Option<Entry>, F::Error> {
+        while cursor.sectors_left > 0 {
+            // a sector not (intactly) started yet holds nothing
+            let started = cursor.slot > 0 || self.read_header(cursor.sector)?.is_some();
+            if started && cursor.slot + 1 < SLOTS_PER_SECTOR {
+                cursor.slot += 1;
+                let bytes = self.read_slot(cursor.sector, cursor.slot)?;
+                // the rest of the sector hasn't been written either
+                if bytes.iter().all(|byte| *byte == 0xFF) {
+                    cursor.slot = SLOTS_PER_SECTOR;
+                    continue;
+                }
+                match Entry::from_bytes(&bytes) {
+                    Some(entry) => return Ok(Some(entry)),
+                    None => continue,
+                }
+            }
+            cursor.sectors_left -= 1;
+            cursor.sector = (cursor.sector + 1) % self.sectors;
+            cursor.slot = 0;
+        }
+        Ok(None)
+    }
+
+    pub fn release(self) -> F {
+        self.flash
+    }
+
+    // Erase `sector` and make it the head
+    fn start_sector(&mut self, sector: u32, sequence: u32) -> Result<(), F::Error> {
+        let from = self.sector_offset(sector);
+        self.flash.erase(from, from + SECTOR_SIZE)?;
+        let mut header = [0; ENTRY_LEN];
+        header[0..4].copy_from_slice(&MAGIC);
+        header[4..6].copy_from_slice(&VERSION.to_le_bytes());
+        header[8..12].copy_from_slice(&sequence.to_le_bytes());
+        let crc = crc32(&header[..12]);
+        header[12..].copy_from_slice(&crc.to_le_bytes());
+        self.program(sector, 0, &header)?;
+        self.head = sector;
+        self.sequence = sequence;
+        self.next = 1;
+        Ok(())
+    }
+
+    // The sequence number of the sector, if its header is intact
+    fn read_header(&mut self, sector: u32) -> Result<Option<u32>, F::Error> {
+        let header = self.read_slot(sector, 0)?;
+        let intact = header[0..4] == MAGIC
+            && u16::from_le_bytes([header[4], header[5]]) == VERSION
+            && u32::from_le_bytes([header[12], header[13], header[14], header[15]]) == crc32(&header[..12]);
+        Ok(intact.then(|| u32::from_le_bytes([header[8], header[9], header[10], header[11]])))
+    }
+
+    fn read_slot(&mut self, sector: u32, slot: u32) -> Result<[u8; ENTRY_LEN], F::Error> {
+        let mut bytes = [0; ENTRY_LEN];
+        self.flash.read(self.slot_offset(sector, slot), &mut bytes)?;
+        Ok(bytes)
+    }
+
+    // Program one (blank) slot, leaving the rest of its page alone
+    fn program(&mut self, sector: u32, slot: u32, bytes: &[u8; ENTRY_LEN]) -> Result<(), F::Error> {
+        let offset = self.slot_offset(sector, slot);
+        let in_page = offset as usize % PAGE_SIZE;
+        let mut page = [0xFF; PAGE_SIZE];
+        page[in_page..in_page + ENTRY_LEN].copy_from_slice(bytes);
+        self.flash.write(offset - in_page as u32, &page)
+    }
+
+    fn sector_offset(&self, sector: u32) -> u32 {
+        self.start + sector * SECTOR_SIZE
+    }
+
+    fn slot_offset(&self, sector: u32, slot: u32) -> u32 {
+        self.sector_offset(sector) + slot * ENTRY_LEN as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ram_flash::RamFlash;
+    use embedded_storage::nor_flash::{NorFlashError, NorFlashErrorKind};
+
+    // The log starts one sector in, so the sectors around it must survive
+    const START: u32 = SECTOR_SIZE;
+    const END: u32 = 4 * SECTOR_SIZE;
+
+    fn opened(flash: RamFlash) -> DataLog<RamFlash> {
+        DataLog::open(flash, START, END).unwrap()
+    }
+
+    fn at(secs: u64) -> Instant {
+        Instant::from_micros(secs * 1_000_000)
+    }
+
+    fn reading(n: u32) -> Reading {
+        Reading {
+            temp: n as f32 / 4.0 - 20.0,
+            hum: (n % 100) as f32,
+        }
+    }
+
+    // Log readings numbered from..to, one a second
+    fn append_readings(log: &mut DataLog<RamFlash>, from: u32, to: u32) {
+        for n in from..to {
+            log.append(at(n as u64), Some(&reading(n)), 0).unwrap();
+        }
+    }
+
+    fn dumped(log: &mut DataLog<RamFlash>) -> Vec<Entry> {
+        let mut cursor = log.oldest();
+        let mut entries = Vec::new();
+        while let Some(entry) = log.read_next(&mut cursor).unwrap() {
+            entries.push(entry);
+        }
+        entries
+    }
+
+    #[test]
+    fn blank_flash_starts_an_empty_log() {
+        let mut log = opened(RamFlash::multiwrite(5));
+        assert_eq!(log.boot(), 0);
+        assert_eq!(dumped(&mut log), []);
+        let flash = log.release();
+        assert_eq!(flash.erases, [START]);
+    }
+
+    #[test]
+    fn entries_read_back_oldest_first() {
+        let mut log = opened(RamFlash::multiwrite(5));
+        append_readings(&mut log, 0, 10);
+        log.append(at(10), None, 0b101).unwrap();
+
+        let entries = dumped(&mut log);
+        assert_eq!(entries.len(), 11);
+        for (n, entry) in entries[..10].iter().enumerate() {
+            assert_eq!(entry.uptime_secs, n as u32);
+            assert_eq!(entry.reading, Some(reading(n as u32)));
+        }
+        assert_eq!(
+            entries[10],
+            Entry {
+                boot: 0,
+                uptime_secs: 10,
+                reading: None,
+                faults: 0b101,
+            }
+        );
+    }
+
+    #[test]
+    fn readings_are_kept_to_hundredths() {
+        let mut log = opened(RamFlash::multiwrite(5));
+        log.append(at(0), Some(&Reading { temp: -12.3456, hum: 45.678 }), 0).unwrap();
+        let entry = &dumped(&mut log)[0];
+        let reading = entry.reading.as_ref().unwrap();
+        assert_eq!(reading.temp, -1235.0 / 100.0);
+        assert_eq!(reading.hum, 4568.0 / 100.0);
+    }
+
+    #[test]
+    fn the_oldest_sector_is_erased_when_the_log_is_full() {
+        let mut log = opened(RamFlash::multiwrite(5));
+        let total = 3 * ENTRIES_PER_SECTOR + 10;
+        append_readings(&mut log, 0, total);
+
+        // three sectors: after filling them, the first was erased for the last 10
+        let entries = dumped(&mut log);
+        assert_eq!(entries.len() as u32, 2 * ENTRIES_PER_SECTOR + 10);
+        assert_eq!(entries[0].uptime_secs, ENTRIES_PER_SECTOR);
+        assert_eq!(entries.last().unwrap().uptime_secs, total - 1);
+        assert!(entries.windows(2).all(|pair| pair[1].uptime_secs == pair[0].uptime_secs + 1));
+        assert!(log.capacity() <= entries.len() as u32);
+
+        let flash = log.release();
+        assert_eq!(flash.erases, [START, START + SECTOR_SIZE, START + 2 * SECTOR_SIZE, START]);
+        assert!(flash.bytes[..START as usize].iter().all(|byte| *byte == 0xFF));
+        assert!(flash.bytes[END as usize..].iter().all(|byte| *byte == 0xFF));
+    }
+
+    #[test]
+    fn logging_carries_on_after_a_reset() {
+        let mut log = opened(RamFlash::multiwrite(5));
+        append_readings(&mut log, 0, 5);
+        let mut log = opened(log.release());
+        assert_eq!(log.boot(), 1);
+        append_readings(&mut log, 0, ENTRIES_PER_SECTOR);
+        let mut log = opened(log.release());
+        assert_eq!(log.boot(), 2);
+        append_readings(&mut log, 0, 3);
+
+        let entries = dumped(&mut log);
+        assert_eq!(entries.len() as u32, 5 + ENTRIES_PER_SECTOR + 3);
+        let boots: Vec<u16> = entries.iter().map(|entry| entry.boot).collect();
+        assert_eq!(boots[..5], [0; 5]);
+        assert_eq!(boots[5..entries.len() - 3], vec![1; ENTRIES_PER_SECTOR as usize]);
+        assert_eq!(boots[entries.len() - 3..], [2; 3]);
+    }
+
+    #[test]
+    fn boot_number_survives_a_freshly_started_sector() {
+        let mut log = opened(RamFlash::multiwrite(5));
+        append_readings(&mut log, 0, ENTRIES_PER_SECTOR + 1);
+        let mut flash = log.release();
+        // the only entry in the new head is lost to a power cut
+        let second = (START + SECTOR_SIZE) as usize;
+        flash.bytes[second + ENTRY_LEN..second + 2 * ENTRY_LEN].fill(0xFF);
+
+        let log = opened(flash);
+        assert_eq!(log.boot(), 1);
+    }
+
+    #[test]
+    fn torn_entries_are_skipped() {
+        let mut log = opened(RamFlash::multiwrite(5));
+        append_readings(&mut log, 0, 3);
+        let mut flash = log.release();
+        // power cut while programming the last entry
+        let third = START as usize + 3 * ENTRY_LEN;
+        flash.bytes[third + 8..third + ENTRY_LEN].fill(0xFF);
+
+        let mut log = opened(flash);
+        append_readings(&mut log, 3, 5);
+        let uptimes: Vec<u32> = dumped(&mut log).iter().map(|entry| entry.uptime_secs).collect();
+        assert_eq!(uptimes, [0, 1, 3, 4]);
+    }
+
+    #[test]
+    fn interrupted_rollover_is_redone() {
+        let mut log = opened(RamFlash::multiwrite(5));
+        append_readings(&mut log, 0, ENTRIES_PER_SECTOR + 1);
+        let mut flash = log.release();
+        // power cut between erasing the second sector and writing its header
+        let second = (START + SECTOR_SIZE) as usize;
+        flash.bytes[second..second + SECTOR_SIZE as usize].fill(0xFF);
+
+        let mut log = opened(flash);
+        assert_eq!(dumped(&mut log).len() as u32, ENTRIES_PER_SECTOR);
+        append_readings(&mut log, 0, 1);
+        assert_eq!(dumped(&mut log).len() as u32, ENTRIES_PER_SECTOR + 1);
+        assert_eq!(log.release().erases, [START, START + SECTOR_SIZE, START + SECTOR_SIZE]);
+    }
+
+    #[test]
+    fn entries_are_written_as_csv() {
+        let mut out = std::string::String::new();
+        out.push_str(CSV_HEADER);
+        let logged = Entry {
+            boot: 3,
+            uptime_secs: 3600,
+            reading: Some(Reading { temp: -0.05, hum: 45.1 }),
+            faults: 0,
+        };
+        logged.write_csv(&mut out).unwrap();
+        let failed = Entry {
+            boot: 3,
+            uptime_secs: 3610,
+            reading: None,
+            faults: 1 << FaultCode::SensorNack.index() | 1 << FaultCode::LcdNack.index(),
+        };
+        failed.write_csv(&mut out).unwrap();
+        assert_eq!(
+            out,
+            "boot,uptime_s,temperature_c,humidity_rh,faults\r\n\
+             3,3600,-0.05,45.10,\r\n\
+             3,3610,,,SensorNack LcdNack\r\n"
+        );
+
+        let mut reader = csv::Reader::from_reader(out.as_bytes());
+        let records: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(records[0][2].parse::<f32>().unwrap(), -0.05);
+        assert_eq!(&records[1][4], "SensorNack LcdNack");
+    }
+
+    #[test]
+    fn dump_goes_out_as_fast_as_it_is_taken() {
+        let mut log = opened(RamFlash::multiwrite(5));
+        append_readings(&mut log, 0, ENTRIES_PER_SECTOR + 20);
+        let mut expected = std::string::String::from(CSV_HEADER);
+        for entry in dumped(&mut log) {
+            entry.write_csv(&mut expected).unwrap();
+        }
+
+        // a port taking up to 50 bytes per pass of the main loop
+        let mut out = Vec::new();
+        let mut dump = Dump::new(&log);
+        let mut passes = 0;
+        loop {
+            let mut room = 50;
+            let more = dump
+                .send(&mut log, |bytes| {
+                    let taken = bytes.len().min(room);
+                    out.extend_from_slice(&bytes[..taken]);
+                    room -= taken;
+                    taken
+                })
+                .unwrap();
+            passes += 1;
+            if !more {
+                break;
+            }
+        }
+        assert_eq!(std::string::String::from_utf8(out).unwrap(), expected);
+        assert_eq!(passes, expected.len().div_ceil(50));
+    }
+
+    #[test]
+    fn flash_errors_are_passed_on() {
+        // a log running past the end of the flash
+        let error = DataLog::open(RamFlash::multiwrite(2), START, END).err().unwrap();
+        let OpenError::Flash(error) = error else {
+            panic!("expected a flash error, got {:?}", error);
+        };
+        assert_eq!(error.kind(), NorFlashErrorKind::OutOfBounds);
+    }
+
+    #[test]
+    fn regions_that_cant_hold_a_log_are_refused() {
+        // not sector aligned, a single sector, and the wrong way round
+        for (start, end) in [(START + 256, END), (START, END - 1), (START, START + SECTOR_SIZE), (END, START)] {
+            let error = DataLog::open(RamFlash::multiwrite(5), start, end).err().unwrap();
+            assert_eq!(error, OpenError::BadRegion);
+        }
+    }
+}
@@ -0,0 +1,478 @@
+/*
+cite: source dht20 v0.1.0 crate for rust embedded DHT20 sensor
+This module forks the dht20 crate so the sensor can share the board's delay with the LCD
+This file modifies the fork of the published crate, less the extra feature code for non-pertinent embedded-hal version
+URL: https://github.com/MnlPhlp/dht20
+URL: https://crates.io/crates/dht20
+The sensor owns its DELAY by value. The firmware's DelayTimer is a Copy handle onto the shared timer,
+so the board gives the sensor a copy of the same delay the LCD borrows (see board.rs)
+*/
+
+use core::fmt;
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+
+// use embedded_hal::delay::DelayNs;
+// use embedded_hal::i2c::{I2c, SevenBitAddress};
+
+use log::info;
+
+use crate::time::{Clock, Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading {
+    pub temp: f32,
+    pub hum: f32,
+}
+
+impl Reading {
+    // Convert a raw measurement frame from the sensor into a reading. The
+    // humidity and temperature are both 20-bit values packed across bytes 1-5:
+    //   humidity:    data[1], data[2], upper nibble of data[3]
+    //   temperature: lower nibble of data[3], data[4], data[5]
+    pub fn from_raw(data: &[u8; 8]) -> Self {
+        let mut raw = (data[1] as u32) << 8;
+        raw += data[2] as u32;
+        raw <<= 4;
+        raw += (data[3] >> 4) as u32;
+        let hum = raw as f32 * (100.0 / 1048576.0); // 20-bit value scaled to 0-100 %
+
+        let mut raw = (data[3] & 0x0F) as u32;
+        raw <<= 8;
+        raw += data[4] as u32;
+        raw <<= 8;
+        raw += data[5] as u32;
+        let temp = raw as f32 * (200.0 / 1048576.0) - 50.0; // 20-bit value scaled to -50-150 C
+        Reading { temp, hum }
+    }
+
+    // Whether the reading is inside the sensor's rated range (-40 to 80 C,
+    // 0 to 100 %RH). Anything outside it points at a faulty or disturbed
+    // sensor even though the frame itself was valid.
+    pub fn in_range(&self) -> bool {
+        (-40.0..=80.0).contains(&self.temp) && (0.0..=100.0).contains(&self.hum)
+    }
+}
+
+#[derive(Debug)]
+pub enum Error<E: fmt::Debug> {
+    // The bus transaction failed, usually because the sensor did not ACK
+    I2cError(E),
+    // The calibration bit was still clear after (re)initializing the sensor
+    NotCalibrated,
+    // read() was called again before MIN_READ_INTERVAL had passed
+    ReadTooFast,
+    // The CRC byte sent after the measurement did not match the data bytes,
+    // i.e. the frame was corrupted on the bus
+    CrcMismatch,
+    // The sensor was still reporting busy when the measurement timeout ran out
+    Timeout,
+}
+
+impl<E: fmt::Debug> Error<E> {
+    // Short description of the error that fits on one line of the 16x2 LCD
+    pub fn description(&self) -> &'static str {
+        match self {
+            Error::I2cError(_) => "Sensor NACK",
+            Error::NotCalibrated => "Not calibrated",
+            Error::ReadTooFast => "Read too fast",
+            Error::CrcMismatch => "CRC mismatch",
+            Error::Timeout => "Sensor timeout",
+        }
+    }
+}
+
+// Bit 7 of the status byte is set while a measurement is in progress, bit 3
+// once the sensor's calibration coefficients are loaded
+const STATUS_BUSY: u8 = 0x80;
+const STATUS_CALIBRATED: u8 = 0x08;
+
+// The datasheet recommends at least 2 s between measurements to keep the
+// sensor from self-heating
+pub const MIN_READ_INTERVAL: Duration = Duration::from_millis(2000);
+
+// How long to wait between busy-bit polls, and the default limit on how long
+// a measurement may take (the datasheet quotes a typical 80 ms conversion)
+const POLL_INTERVAL_MS: u16 = 5;
+pub const DEFAULT_TIMEOUT_MS: u16 = 200;
+
+// CRC-8 as specified in the DHT20/AHT20 datasheet: polynomial 0x31
+// (x^8 + x^5 + x^4 + 1), initial value 0xFF, no reflection or final XOR
+pub fn crc8(data: &[u8]) -> u8 {
+    let mut crc: u8 = 0xFF;
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            if crc & 0x80 != 0 {
+                crc = (crc << 1) ^ 0x31;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
+// updated Dht20 with a shareable DELAY handle
+pub struct Dht20<'a, I2C, DELAY, CLOCK, E>
+where
+    I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+    DELAY: DelayMs<u16>,
+    CLOCK: Clock,
+    // I2C: I2c<SevenBitAddress, Error=E>,
+    // DELAY: DelayNs,
+    E: fmt::Debug,
+{
+    i2c: I2C,
+    address: u8,
+    delay: DELAY,
+    clock: &'a CLOCK,
+    timeout_ms: u16,
+    // time the last measurement was triggered, used to enforce MIN_READ_INTERVAL
+    last_read: Option<Instant>,
+}
+// updated Dht20 with a shareable DELAY handle
+impl<'a, I2C, DELAY, CLOCK, E> Dht20<'a, I2C, DELAY, CLOCK, E>
+where
+    I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+    DELAY: DelayMs<u16>,
+    CLOCK: Clock,
+    // I2C: I2c<SevenBitAddress, Error=E>,
+    // DELAY: DelayNs,
+    E: fmt::Debug,
+{
+    pub fn new(i2c: I2C, address: u8, delay: DELAY, clock: &'a CLOCK) -> Self {
+        Self {
+            i2c,
+            address,
+            delay,
+            clock,
+            timeout_ms: DEFAULT_TIMEOUT_MS,
+            last_read: None,
+        }
+    }
+
+    // Change how long read() waits for a measurement to complete before
+    // giving up with Error::Timeout
+    pub fn set_timeout_ms(&mut self, timeout_ms: u16) {
+        self.timeout_ms = timeout_ms;
+    }
+
+    // When read() may next be called without failing with ReadTooFast
+    // (None if a measurement has never been triggered)
+    pub fn next_read_at(&self) -> Option<Instant> {
+        self.last_read.map(|last| last + MIN_READ_INTERVAL)
+    }
+
+    pub fn read(&mut self) -> Result<Reading, Error<E>> {
+        if self.next_read_at().is_some_and(|at| self.clock.now() < at) {
+            return Err(Error::ReadTooFast);
+        }
+
+        self.reset()?;
+        // request reading; a read that fails before this point hasn't
+        // started a measurement, so doesn't hold up the next one
+        self.write_data(&[0xAC, 0x33, 0])?;
+        self.last_read = Some(self.clock.now());
+        self.wait_until_ready()?;
+        // read data
+        let data = self.read_data()?;
+        // the sensor sends a CRC of the status and data bytes right after them
+        if crc8(&data[..6]) != data[6] {
+            return Err(Error::CrcMismatch);
+        }
+        // convert values
+        Ok(Reading::from_raw(&data))
+    }
+
+    fn reset(&mut self) -> Result<(), Error<E>> {
+        let status = self.read_status()?;
+        if status & 0x18 != 0x18 {
+            info!("resetting");
+            self.write_data(&[0x1B, 0, 0])?;
+            self.write_data(&[0x1C, 0, 0])?;
+            self.write_data(&[0x1E, 0, 0])?;
+            self.delay.delay_ms(10);
+            if self.read_status()? & STATUS_CALIBRATED == 0 {
+                return Err(Error::NotCalibrated);
+            }
+        }
+        Ok(())
+    }
+
+    // Poll the busy bit until the measurement completes or the timeout expires
+    fn wait_until_ready(&mut self) -> Result<(), Error<E>> {
+        let mut waited_ms: u16 = 0;
+        loop {
+            self.delay.delay_ms(POLL_INTERVAL_MS);
+            waited_ms = waited_ms.saturating_add(POLL_INTERVAL_MS);
+
+            if self.read_status()? & STATUS_BUSY == 0 {
+                return Ok(());
+            }
+            if waited_ms >= self.timeout_ms {
+                return Err(Error::Timeout);
+            }
+        }
+    }
+
+    fn read_data(&mut self) -> Result<[u8; 8], Error<E>> {
+        let mut buffer = [0; 8];
+        self.i2c
+            .read(self.address, &mut buffer)
+            .map_err(Error::I2cError)?;
+        Ok(buffer)
+    }
+
+    fn read_status(&mut self) -> Result<u8, Error<E>> {
+        let mut buffer = [0; 1];
+        self.i2c
+            .read(self.address, &mut buffer)
+            .map_err(Error::I2cError)?;
+        Ok(buffer[0])
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> Result<(), Error<E>> {
+        self.i2c.write(self.address, data).map_err(Error::I2cError)
+    }
+    // this is a workaround for the parallel calls of the delay function, my change to this file
+    pub fn delay_ms(&mut self, ms: u16) {
+        self.delay.delay_ms(ms);
+    }
+    // The sensor's own delay, e.g. to wait between steps outside read()
+    pub fn delay(&mut self) -> &mut DELAY {
+        &mut self.delay
+    }
+    // end of changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Frame captured from a DHT20 at roughly 23.4 C / 45.2 %RH
+    const GOOD_FRAME: [u8; 8] = [0x1C, 0x73, 0xB6, 0x45, 0xDF, 0x3B, 0x06, 0x00];
+    // The same frame with a single bit flipped in the humidity bytes
+    const CORRUPTED_FRAME: [u8; 8] = [0x1C, 0x73, 0xB7, 0x45, 0xDF, 0x3B, 0x06, 0x00];
+
+    // Minimal I2C bus double: every read returns the next queued frame and
+    // writes are accepted and ignored
+    struct MockI2c {
+        reads: Vec<Vec<u8>>,
+    }
+
+    impl MockI2c {
+        fn new(reads: &[&[u8]]) -> Self {
+            Self {
+                reads: reads.iter().rev().map(|r| r.to_vec()).collect(),
+            }
+        }
+    }
+
+    impl Read for MockI2c {
+        type Error = ();
+
+        fn read(&mut self, _address: u8, buffer: &mut [u8]) -> Result<(), ()> {
+            let next = self.reads.pop().ok_or(())?;
+            buffer.copy_from_slice(&next[..buffer.len()]);
+            Ok(())
+        }
+    }
+
+    impl Write for MockI2c {
+        type Error = ();
+
+        fn write(&mut self, _address: u8, _bytes: &[u8]) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    impl WriteRead for MockI2c {
+        type Error = ();
+
+        fn write_read(&mut self, address: u8, _bytes: &[u8], buffer: &mut [u8]) -> Result<(), ()> {
+            self.read(address, buffer)
+        }
+    }
+
+    // Records the total time the driver asked to wait
+    #[derive(Default)]
+    struct MockDelay {
+        total_ms: u32,
+    }
+
+    impl DelayMs<u16> for MockDelay {
+        fn delay_ms(&mut self, ms: u16) {
+            self.total_ms += ms as u32;
+        }
+    }
+
+    // Clock that only moves when a test advances it
+    struct MockClock {
+        now: core::cell::Cell<Instant>,
+    }
+
+    impl Default for MockClock {
+        fn default() -> Self {
+            Self {
+                now: core::cell::Cell::new(Instant::from_micros(0)),
+            }
+        }
+    }
+
+    impl MockClock {
+        fn advance(&self, duration: Duration) {
+            self.now.set(self.now.get() + duration);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            self.now.get()
+        }
+    }
+
+    const IDLE: &[u8] = &[0x18];
+    const UNCALIBRATED: &[u8] = &[0x10];
+    const BUSY: &[u8] = &[0x98];
+
+    #[test]
+    fn crc8_matches_reference_values() {
+        assert_eq!(crc8(&[]), 0xFF);
+        assert_eq!(crc8(&[0xBE, 0xEF]), 0x92);
+        assert_eq!(crc8(&GOOD_FRAME[..6]), GOOD_FRAME[6]);
+    }
+
+    #[test]
+    fn read_accepts_good_frame() {
+        let delay = MockDelay::default();
+        let clock = MockClock::default();
+        let i2c = MockI2c::new(&[IDLE, IDLE, &GOOD_FRAME]);
+        let mut sensor = Dht20::new(i2c, 0x38, delay, &clock);
+
+        let reading = sensor.read().unwrap();
+        assert!((reading.hum - 45.2).abs() < 0.01);
+    }
+
+    #[test]
+    fn read_rejects_corrupted_frame() {
+        let delay = MockDelay::default();
+        let clock = MockClock::default();
+        let i2c = MockI2c::new(&[IDLE, IDLE, &CORRUPTED_FRAME]);
+        let mut sensor = Dht20::new(i2c, 0x38, delay, &clock);
+
+        assert!(matches!(sensor.read(), Err(Error::CrcMismatch)));
+    }
+
+    #[test]
+    fn read_surfaces_bus_errors() {
+        let delay = MockDelay::default();
+        let clock = MockClock::default();
+        let i2c = MockI2c::new(&[]);
+        let mut sensor = Dht20::new(i2c, 0x38, delay, &clock);
+
+        assert!(matches!(sensor.read(), Err(Error::I2cError(()))));
+    }
+
+    #[test]
+    fn from_raw_converts_humidity_and_temperature() {
+        let reading = Reading::from_raw(&GOOD_FRAME);
+        assert!((reading.hum - 45.2).abs() < 0.01);
+        assert!((reading.temp - 23.4).abs() < 0.01);
+    }
+
+    #[test]
+    fn from_raw_handles_range_limits() {
+        let reading = Reading::from_raw(&[0x1C, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(reading.hum, 0.0);
+        assert_eq!(reading.temp, -50.0);
+
+        let reading = Reading::from_raw(&[0x1C, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0]);
+        assert!((reading.hum - 100.0).abs() < 0.001);
+        assert!((reading.temp - 150.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn in_range_follows_rated_limits() {
+        assert!(Reading { temp: 23.4, hum: 45.2 }.in_range());
+        assert!(Reading { temp: -40.0, hum: 0.0 }.in_range());
+        assert!(Reading { temp: 80.0, hum: 100.0 }.in_range());
+        assert!(!Reading { temp: 95.0, hum: 45.2 }.in_range());
+        assert!(!Reading { temp: -45.0, hum: 45.2 }.in_range());
+        assert!(!Reading { temp: 23.4, hum: 100.5 }.in_range());
+    }
+
+    #[test]
+    fn read_polls_busy_bit_until_ready() {
+        let delay = MockDelay::default();
+        let clock = MockClock::default();
+        let i2c = MockI2c::new(&[IDLE, BUSY, BUSY, IDLE, &GOOD_FRAME]);
+        let mut sensor = Dht20::new(i2c, 0x38, delay, &clock);
+
+        assert!(sensor.read().is_ok());
+        assert_eq!(sensor.delay().total_ms, 3 * POLL_INTERVAL_MS as u32);
+    }
+
+    #[test]
+    fn read_times_out_while_busy() {
+        let delay = MockDelay::default();
+        let clock = MockClock::default();
+        let mut reads = vec![IDLE];
+        reads.extend([BUSY; 10]);
+        let i2c = MockI2c::new(&reads);
+        let mut sensor = Dht20::new(i2c, 0x38, delay, &clock);
+        sensor.set_timeout_ms(20);
+
+        assert!(matches!(sensor.read(), Err(Error::Timeout)));
+        assert_eq!(sensor.delay().total_ms, 20);
+    }
+
+    #[test]
+    fn read_reports_uncalibrated_sensor() {
+        let delay = MockDelay::default();
+        let clock = MockClock::default();
+        let i2c = MockI2c::new(&[UNCALIBRATED, UNCALIBRATED]);
+        let mut sensor = Dht20::new(i2c, 0x38, delay, &clock);
+
+        assert!(matches!(sensor.read(), Err(Error::NotCalibrated)));
+    }
+
+    #[test]
+    fn read_recovers_after_successful_init() {
+        let delay = MockDelay::default();
+        let clock = MockClock::default();
+        let i2c = MockI2c::new(&[UNCALIBRATED, IDLE, IDLE, &GOOD_FRAME]);
+        let mut sensor = Dht20::new(i2c, 0x38, delay, &clock);
+
+        assert!(sensor.read().is_ok());
+    }
+
+    #[test]
+    fn read_enforces_minimum_interval() {
+        let delay = MockDelay::default();
+        let clock = MockClock::default();
+        let i2c = MockI2c::new(&[IDLE, IDLE, &GOOD_FRAME, IDLE, IDLE, &GOOD_FRAME]);
+        let mut sensor = Dht20::new(i2c, 0x38, delay, &clock);
+
+        assert!(sensor.read().is_ok());
+        clock.advance(MIN_READ_INTERVAL - Duration::from_micros(1));
+        assert!(matches!(sensor.read(), Err(Error::ReadTooFast)));
+        clock.advance(Duration::from_micros(1));
+        assert!(sensor.read().is_ok());
+    }
+
+    #[test]
+    fn read_failing_before_measuring_can_be_retried_straight_away() {
+        let delay = MockDelay::default();
+        let clock = MockClock::default();
+        // nothing to read the first time round, as if the sensor NACKed
+        let i2c = MockI2c::new(&[]);
+        let mut sensor = Dht20::new(i2c, 0x38, delay, &clock);
+
+        assert!(matches!(sensor.read(), Err(Error::I2cError(()))));
+        assert_eq!(sensor.next_read_at(), None);
+
+        sensor.i2c.reads = vec![GOOD_FRAME.to_vec(), IDLE.to_vec(), IDLE.to_vec()];
+        assert!(sensor.read().is_ok());
+        assert_eq!(sensor.next_read_at(), Some(Instant::from_micros(0) + MIN_READ_INTERVAL));
+    }
+}
@@ -0,0 +1,191 @@
+use core::fmt::{self, Write};
+
+use crate::dht::Reading;
+use crate::psychrometrics;
+use crate::utils::round_to_decimal;
+
+// Width of one row of the 16x2 LCD
+pub const LCD_COLUMNS: usize = 16;
+
+// Unit used when showing temperatures. The sensor always reports Celsius.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemperatureUnit {
+    Celsius,
+    Fahrenheit,
+}
+
+impl TemperatureUnit {
+    // Convert a Celsius temperature into this unit
+    pub fn from_celsius(&self, celsius: f32) -> f32 {
+        match self {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 1.8 + 32.0,
+        }
+    }
+
+    pub fn symbol(&self) -> char {
+        match self {
+            TemperatureUnit::Celsius => 'C',
+            TemperatureUnit::Fahrenheit => 'F',
+        }
+    }
+}
+
+// A single row of LCD text built without heap allocation. Anything written
+// past the width of the display is dropped.
+pub struct LcdLine {
+    bytes: [u8; LCD_COLUMNS],
+    len: usize,
+}
+
+impl LcdLine {
+    pub fn new() -> Self {
+        LcdLine {
+            bytes: [0; LCD_COLUMNS],
+            len: 0,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only whole characters are ever copied in, so this cannot fail
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+impl Default for LcdLine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for LcdLine {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            let width = c.len_utf8();
+            if self.len + width > LCD_COLUMNS {
+                break;
+            }
+            c.encode_utf8(&mut self.bytes[self.len..]);
+            self.len += width;
+        }
+        Ok(())
+    }
+}
+
+// Format a reading as temperature and relative humidity on one LCD row,
+// e.g. "23.4C  45.2%RH"
+pub fn reading_line(reading: &Reading, unit: TemperatureUnit, rounding: u32) -> LcdLine {
+    // Buffer is required by ryu to transform a float into a string
+    let mut buffer = ryu::Buffer::new();
+    let mut line = LcdLine::new();
+
+    let temp = round_to_decimal(unit.from_celsius(reading.temp), rounding);
+    let _ = write!(line, "{}{}  ", buffer.format(temp), unit.symbol());
+
+    let hum = round_to_decimal(reading.hum, rounding);
+    let _ = write!(line, "{}%RH", buffer.format(hum));
+
+    line
+}
+
+// Metrics derived from a reading, each shown on its own LCD page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    DewPoint,
+    AbsoluteHumidity,
+    HeatIndex,
+    Humidex,
+}
+
+impl Metric {
+    // Every metric, in the order the pages cycle through them
+    pub const ALL: [Metric; 4] = [
+        Metric::DewPoint,
+        Metric::AbsoluteHumidity,
+        Metric::HeatIndex,
+        Metric::Humidex,
+    ];
+
+    // Page title shown on the first LCD row
+    pub fn title(&self) -> &'static str {
+        match self {
+            Metric::DewPoint => "Dew Point",
+            Metric::AbsoluteHumidity => "Abs. Humidity",
+            Metric::HeatIndex => "Heat Index",
+            Metric::Humidex => "Humidex",
+        }
+    }
+}
+
+// Format a derived metric for the second LCD row, e.g. "13.9C" or "11.5 g/m3"
+pub fn metric_line(metric: Metric, reading: &Reading, unit: TemperatureUnit, rounding: u32) -> LcdLine {
+    let mut buffer = ryu::Buffer::new();
+    let mut line = LcdLine::new();
+
+    let _ = match metric {
+        Metric::DewPoint => {
+            let value = unit.from_celsius(psychrometrics::dew_point(reading));
+            write!(line, "{}{}", buffer.format(round_to_decimal(value, rounding)), unit.symbol())
+        }
+        Metric::AbsoluteHumidity => {
+            // the LCD character set has no superscript 3
+            let value = psychrometrics::absolute_humidity(reading);
+            write!(line, "{} g/m3", buffer.format(round_to_decimal(value, rounding)))
+        }
+        Metric::HeatIndex => {
+            let value = unit.from_celsius(psychrometrics::heat_index(reading));
+            write!(line, "{}{}", buffer.format(round_to_decimal(value, rounding)), unit.symbol())
+        }
+        Metric::Humidex => {
+            // humidex is a unitless index, so it is not converted
+            let value = psychrometrics::humidex(reading);
+            write!(line, "{}", buffer.format(round_to_decimal(value, rounding)))
+        }
+    };
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_celsius_to_fahrenheit() {
+        assert_eq!(TemperatureUnit::Celsius.from_celsius(23.4), 23.4);
+        assert_eq!(TemperatureUnit::Fahrenheit.from_celsius(0.0), 32.0);
+        assert_eq!(TemperatureUnit::Fahrenheit.from_celsius(100.0), 212.0);
+    }
+
+    #[test]
+    fn reading_line_shows_temperature_and_humidity() {
+        let reading = Reading { temp: 23.44, hum: 45.21 };
+        assert_eq!(
+            reading_line(&reading, TemperatureUnit::Celsius, 1).as_str(),
+            "23.4C  45.2%RH"
+        );
+        assert_eq!(
+            reading_line(&reading, TemperatureUnit::Fahrenheit, 1).as_str(),
+            "74.2F  45.2%RH"
+        );
+    }
+
+    #[test]
+    fn lcd_line_truncates_to_display_width() {
+        let mut line = LcdLine::new();
+        let _ = line.write_str("0123456789abcdefXYZ");
+        assert_eq!(line.as_str(), "0123456789abcdef");
+    }
+
+    #[test]
+    fn metric_line_formats_each_metric() {
+        let reading = Reading { temp: 25.0, hum: 50.0 };
+        let line = |metric, unit| metric_line(metric, &reading, unit, 1);
+
+        assert_eq!(line(Metric::DewPoint, TemperatureUnit::Celsius).as_str(), "13.9C");
+        assert_eq!(line(Metric::DewPoint, TemperatureUnit::Fahrenheit).as_str(), "56.9F");
+        assert_eq!(line(Metric::AbsoluteHumidity, TemperatureUnit::Celsius).as_str(), "11.5 g/m3");
+        assert_eq!(line(Metric::HeatIndex, TemperatureUnit::Celsius).as_str(), "24.9C");
+        assert_eq!(line(Metric::Humidex, TemperatureUnit::Fahrenheit).as_str(), "28.3");
+    }
+}
@@ -0,0 +1,267 @@
+use core::fmt::{self, Write};
+
+use crate::dht::Reading;
+use crate::display::TemperatureUnit;
+use crate::report;
+use crate::time::Instant;
+
+// Formats readings can be streamed in: the human readable lines of the
+// report module, or one of two machine formats for loading captures into
+// analysis tools. The machine formats always carry the sensor's own values,
+// in Celsius and unrounded, so they don't change with the display settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    // Comma separated values, after a header row
+    Csv,
+    // One JSON object per line (https://jsonlines.org)
+    JsonLines,
+}
+
+impl Format {
+    pub const ALL: [Format; 3] = [Format::Text, Format::Csv, Format::JsonLines];
+
+    // Name used on the console
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Text => "text",
+            Format::Csv => "csv",
+            Format::JsonLines => "json",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Format> {
+        Format::ALL.into_iter().find(|format| format.name() == name)
+    }
+}
+
+// Identifies the board a capture came from (the Pico's flash chip has a
+// unique 64-bit ID). Written as 16 hex digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DeviceId(pub [u8; 8]);
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+pub const CSV_HEADER: &str = "device,timestamp_ms,temperature_c,humidity_rh,error\r\n";
+
+// Written at the start of each capture; only CSV has a header
+pub fn write_header<W: Write>(out: &mut W, format: Format) -> fmt::Result {
+    match format {
+        Format::Csv => out.write_str(CSV_HEADER),
+        Format::Text | Format::JsonLines => Ok(()),
+    }
+}
+
+// e.g. "e6614103e7452d2f,12345,23.44,45.16,"
+//   or {"device":"e6614103e7452d2f","timestamp_ms":12345,"temperature_c":23.44,"humidity_rh":45.16}
+// The unit and rounding only apply to the text format.
+pub fn write_reading<W: Write>(
+    out: &mut W,
+    format: Format,
+    device: &DeviceId,
+    timestamp: Instant,
+    reading: &Reading,
+    unit: TemperatureUnit,
+    rounding: u32,
+) -> fmt::Result {
+    let millis = timestamp.as_micros() / 1_000;
+    match format {
+        Format::Text => report::write_reading(out, timestamp, reading, unit, rounding),
+        Format::Csv => {
+            write!(out, "{},{},", device, millis)?;
+            write_number(out, reading.temp, "")?;
+            out.write_char(',')?;
+            write_number(out, reading.hum, "")?;
+            out.write_str(",\r\n")
+        }
+        Format::JsonLines => {
+            write!(out, "{{\"device\":\"{}\",\"timestamp_ms\":{},\"temperature_c\":", device, millis)?;
+            write_number(out, reading.temp, "null")?;
+            out.write_str(",\"humidity_rh\":")?;
+            write_number(out, reading.hum, "null")?;
+            out.write_str("}\r\n")
+        }
+    }
+}
+
+// A failed reading, with the values left empty (CSV) or out (JSON)
+pub fn write_error<W: Write>(
+    out: &mut W,
+    format: Format,
+    device: &DeviceId,
+    timestamp: Instant,
+    description: &str,
+) -> fmt::Result {
+    let millis = timestamp.as_micros() / 1_000;
+    match format {
+        Format::Text => report::write_error(out, timestamp, description),
+        Format::Csv => {
+            write!(out, "{},{},,,", device, millis)?;
+            write_csv_field(out, description)?;
+            out.write_str("\r\n")
+        }
+        Format::JsonLines => {
+            write!(out, "{{\"device\":\"{}\",\"timestamp_ms\":{},\"error\":", device, millis)?;
+            write_json_string(out, description)?;
+            out.write_str("}\r\n")
+        }
+    }
+}
+
+// Shortest form that reads back as the same f32. Neither format can hold
+// NaN or infinity, so those are written as `missing` instead (a reading from
+// the sensor is always finite, though).
+fn write_number<W: Write>(out: &mut W, value: f32, missing: &str) -> fmt::Result {
+    if value.is_finite() {
+        out.write_str(ryu::Buffer::new().format_finite(value))
+    } else {
+        out.write_str(missing)
+    }
+}
+
+// Quote the field if it holds a separator, quote or line break
+fn write_csv_field<W: Write>(out: &mut W, field: &str) -> fmt::Result {
+    if !field.contains([',', '"', '\r', '\n']) {
+        return out.write_str(field);
+    }
+    out.write_char('"')?;
+    for c in field.chars() {
+        if c == '"' {
+            out.write_char('"')?;
+        }
+        out.write_char(c)?;
+    }
+    out.write_char('"')
+}
+
+fn write_json_string<W: Write>(out: &mut W, s: &str) -> fmt::Result {
+    out.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEVICE: DeviceId = DeviceId([0xe6, 0x61, 0x41, 0x03, 0xe7, 0x45, 0x2d, 0x2f]);
+
+    fn readings() -> [Reading; 3] {
+        [
+            Reading { temp: 23.44, hum: 45.16 },
+            Reading { temp: -12.345678, hum: 0.0 },
+            Reading { temp: 85.0, hum: 99.99999 },
+        ]
+    }
+
+    fn at(millis: u64) -> Instant {
+        Instant::from_micros(millis * 1_000)
+    }
+
+    // A capture of the readings, one second apart, with a failed read after them
+    fn capture(format: Format) -> String {
+        let mut out = String::new();
+        write_header(&mut out, format).unwrap();
+        for (i, reading) in readings().iter().enumerate() {
+            let timestamp = at(1_000 * i as u64);
+            write_reading(&mut out, format, &DEVICE, timestamp, reading, TemperatureUnit::Fahrenheit, 1)
+                .unwrap();
+        }
+        write_error(&mut out, format, &DEVICE, at(3_000), "Sensor \"NACK\", retrying").unwrap();
+        out
+    }
+
+    #[test]
+    fn device_id_is_hex() {
+        assert_eq!(DEVICE.to_string(), "e6614103e7452d2f");
+        assert_eq!(DeviceId::default().to_string(), "0000000000000000");
+    }
+
+    #[test]
+    fn formats_found_by_name() {
+        for format in Format::ALL {
+            assert_eq!(Format::from_name(format.name()), Some(format));
+        }
+        assert_eq!(Format::from_name("xml"), None);
+    }
+
+    #[test]
+    fn text_is_the_report_format() {
+        let capture = capture(Format::Text);
+        assert!(capture.starts_with("0.000 s  74.2 F  45.2 %RH\r\n"));
+        assert!(capture.ends_with("3.000 s  error: Sensor \"NACK\", retrying\r\n"));
+    }
+
+    #[test]
+    fn csv_round_trips() {
+        let capture = capture(Format::Csv);
+        let mut reader = csv::Reader::from_reader(capture.as_bytes());
+        assert_eq!(
+            reader.headers().unwrap(),
+            vec!["device", "timestamp_ms", "temperature_c", "humidity_rh", "error"]
+        );
+
+        let records: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(records.len(), 4);
+        for (i, (record, reading)) in records.iter().zip(readings()).enumerate() {
+            assert_eq!(&record[0], "e6614103e7452d2f");
+            assert_eq!(record[1].parse::<u64>().unwrap(), 1_000 * i as u64);
+            assert_eq!(record[2].parse::<f32>().unwrap(), reading.temp);
+            assert_eq!(record[3].parse::<f32>().unwrap(), reading.hum);
+            assert_eq!(&record[4], "");
+        }
+
+        let error = &records[3];
+        assert_eq!(&error[1], "3000");
+        assert_eq!(&error[2], "");
+        assert_eq!(&error[3], "");
+        assert_eq!(&error[4], "Sensor \"NACK\", retrying");
+    }
+
+    #[test]
+    fn json_lines_round_trip() {
+        let capture = capture(Format::JsonLines);
+        let lines: Vec<serde_json::Value> = capture
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 4);
+        for (i, (line, reading)) in lines.iter().zip(readings()).enumerate() {
+            assert_eq!(line["device"], "e6614103e7452d2f");
+            assert_eq!(line["timestamp_ms"], 1_000 * i as u64);
+            assert_eq!(line["temperature_c"].as_f64().unwrap() as f32, reading.temp);
+            assert_eq!(line["humidity_rh"].as_f64().unwrap() as f32, reading.hum);
+            assert!(line.get("error").is_none());
+        }
+
+        let error = &lines[3];
+        assert_eq!(error["timestamp_ms"], 3_000);
+        assert_eq!(error["error"], "Sensor \"NACK\", retrying");
+        assert!(error.get("temperature_c").is_none());
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        let mut out = String::new();
+        write_json_string(&mut out, "a\\b\n\u{1}").unwrap();
+        assert_eq!(out, "\"a\\\\b\\n\\u0001\"");
+        assert_eq!(serde_json::from_str::<String>(&out).unwrap(), "a\\b\n\u{1}");
+    }
+}
@@ -0,0 +1,134 @@
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+use embedded_hal::blocking::i2c::Write;
+
+// Bring a 16x2 HD44780 LCD on a PCF8574 I2C backpack back into a known state
+// by writing its power-on initialization sequence directly over the bus. The
+// LCD driver only does this when it is created, so this is what lets the LCD
+// come back after an I2C bus recovery (a glitch can leave the controller
+// out of step with the 4-bit nibbles) without rebuilding the driver.
+
+// PCF8574 output bits on the common backpack: enable and backlight, with
+// D4-D7 on the upper four bits (register select stays low for commands)
+const ENABLE: u8 = 0x04;
+const BACKLIGHT: u8 = 0x08;
+
+// Commands sent once the controller is in 4-bit mode
+const FUNCTION_SET_4BIT_2LINE: u8 = 0x28;
+const DISPLAY_ON: u8 = 0x0C;
+const CLEAR_DISPLAY: u8 = 0x01;
+const ENTRY_MODE_LEFT_TO_RIGHT: u8 = 0x06;
+
+// Latch the upper nibble of `bits` into the controller with an enable pulse
+fn write_nibble<I, D>(i2c: &mut I, address: u8, bits: u8, delay: &mut D) -> Result<(), I::Error>
+where
+    I: Write,
+    D: DelayUs<u16>,
+{
+    let bits = (bits & 0xF0) | BACKLIGHT;
+    i2c.write(address, &[bits | ENABLE])?;
+    delay.delay_us(1);
+    i2c.write(address, &[bits])?;
+    delay.delay_us(50);
+    Ok(())
+}
+
+// Send a command byte as two nibbles, high nibble first
+fn write_command<I, D>(i2c: &mut I, address: u8, command: u8, delay: &mut D) -> Result<(), I::Error>
+where
+    I: Write,
+    D: DelayUs<u16>,
+{
+    write_nibble(i2c, address, command, delay)?;
+    write_nibble(i2c, address, command << 4, delay)
+}
+
+// Run the datasheet's "initialization by instruction" sequence, which works
+// whatever mode the controller was left in, then turn the display on,
+// cleared, with the backlight lit
+pub fn reinit<I, D>(i2c: &mut I, address: u8, delay: &mut D) -> Result<(), I::Error>
+where
+    I: Write,
+    D: DelayMs<u8> + DelayUs<u16>,
+{
+    delay.delay_ms(50);
+    // three times "8-bit mode" to resynchronize, then switch to 4-bit
+    write_nibble(i2c, address, 0x30, delay)?;
+    delay.delay_us(4500);
+    write_nibble(i2c, address, 0x30, delay)?;
+    delay.delay_us(4500);
+    write_nibble(i2c, address, 0x30, delay)?;
+    delay.delay_us(150);
+    write_nibble(i2c, address, 0x20, delay)?;
+
+    write_command(i2c, address, FUNCTION_SET_4BIT_2LINE, delay)?;
+    write_command(i2c, address, DISPLAY_ON, delay)?;
+    write_command(i2c, address, CLEAR_DISPLAY, delay)?;
+    delay.delay_ms(2);
+    write_command(i2c, address, ENTRY_MODE_LEFT_TO_RIGHT, delay)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct MockI2c {
+        writes: Vec<(u8, Vec<u8>)>,
+    }
+
+    impl Write for MockI2c {
+        type Error = ();
+
+        fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), ()> {
+            self.writes.push((address, bytes.to_vec()));
+            Ok(())
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayMs<u8> for NoDelay {
+        fn delay_ms(&mut self, _ms: u8) {}
+    }
+
+    impl DelayUs<u16> for NoDelay {
+        fn delay_us(&mut self, _us: u16) {}
+    }
+
+    // The nibbles latched by the controller (the byte written with ENABLE set)
+    fn latched(i2c: &MockI2c) -> Vec<u8> {
+        i2c.writes
+            .iter()
+            .map(|(_, bytes)| bytes[0])
+            .filter(|byte| byte & ENABLE != 0)
+            .collect()
+    }
+
+    #[test]
+    fn sends_init_sequence_to_address() {
+        let mut i2c = MockI2c::default();
+        reinit(&mut i2c, 0x27, &mut NoDelay).unwrap();
+
+        assert!(i2c.writes.iter().all(|(address, _)| *address == 0x27));
+        assert_eq!(
+            latched(&i2c),
+            [
+                0x3C, 0x3C, 0x3C, 0x2C, // resync, then 4-bit mode
+                0x2C, 0x8C, // function set 0x28
+                0x0C, 0xCC, // display on 0x0C
+                0x0C, 0x1C, // clear 0x01
+                0x0C, 0x6C, // entry mode 0x06
+            ]
+        );
+    }
+
+    #[test]
+    fn every_pulse_ends_with_enable_low() {
+        let mut i2c = MockI2c::default();
+        reinit(&mut i2c, 0x27, &mut NoDelay).unwrap();
+
+        for pair in i2c.writes.chunks(2) {
+            assert_eq!(pair[1].1[0], pair[0].1[0] & !ENABLE);
+        }
+    }
+}
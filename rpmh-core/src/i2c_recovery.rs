@@ -0,0 +1,210 @@
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+// Clock pulses needed to walk a device out of any byte it might be stuck in
+// the middle of (8 data bits plus the ACK)
+pub const RECOVERY_PULSES: u8 = 9;
+
+// Consecutive failed transactions before a bus is recovered
+pub const DEFAULT_FAILURE_THRESHOLD: u8 = 3;
+
+// Half of one SCL period while bit-banging, slow enough (about 100 kHz) for
+// any device on the bus
+const HALF_PERIOD_US: u16 = 5;
+
+// Counts consecutive failed transactions on a bus. One bad transaction
+// (e.g. a single NACK from an unplugged sensor) is normal, but a run of them
+// usually means a device is holding SDA low and the bus needs recovering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FailureCounter {
+    consecutive: u8,
+    threshold: u8,
+}
+
+impl FailureCounter {
+    pub fn new(threshold: u8) -> Self {
+        FailureCounter {
+            consecutive: 0,
+            threshold: threshold.max(1),
+        }
+    }
+
+    // Record the outcome of a transaction. Returns true when the failure
+    // threshold has been reached, and starts counting again from zero.
+    pub fn record(&mut self, ok: bool) -> bool {
+        if ok {
+            self.consecutive = 0;
+            return false;
+        }
+        self.consecutive += 1;
+        if self.consecutive >= self.threshold {
+            self.consecutive = 0;
+            return true;
+        }
+        false
+    }
+
+    pub fn consecutive(&self) -> u8 {
+        self.consecutive
+    }
+}
+
+impl Default for FailureCounter {
+    fn default() -> Self {
+        Self::new(DEFAULT_FAILURE_THRESHOLD)
+    }
+}
+
+// Clock SCL (up to RECOVERY_PULSES times) until the device holding SDA low
+// lets go of it, with both lines driven as plain GPIOs. Returns whether SDA
+// was released; send_stop should follow either way.
+pub fn clock_out<SCL, SDA, D>(scl: &mut SCL, sda: &SDA, delay: &mut D) -> bool
+where
+    SCL: OutputPin,
+    SDA: InputPin,
+    D: DelayUs<u16>,
+{
+    for _ in 0..RECOVERY_PULSES {
+        if sda.is_high().unwrap_or(false) {
+            return true;
+        }
+        let _ = scl.set_low();
+        delay.delay_us(HALF_PERIOD_US);
+        let _ = scl.set_high();
+        delay.delay_us(HALF_PERIOD_US);
+    }
+    sda.is_high().unwrap_or(false)
+}
+
+// Put a STOP condition on the bus (SDA rising while SCL is high) so every
+// device goes back to waiting for a START
+pub fn send_stop<SCL, SDA, D>(scl: &mut SCL, sda: &mut SDA, delay: &mut D)
+where
+    SCL: OutputPin,
+    SDA: OutputPin,
+    D: DelayUs<u16>,
+{
+    let _ = scl.set_low();
+    let _ = sda.set_low();
+    delay.delay_us(HALF_PERIOD_US);
+    let _ = scl.set_high();
+    delay.delay_us(HALF_PERIOD_US);
+    let _ = sda.set_high();
+    delay.delay_us(HALF_PERIOD_US);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::{Cell, RefCell};
+    use core::convert::Infallible;
+
+    // Records every edge on both lines. The simulated device holds SDA low
+    // until it has seen `stuck_for` rising edges on SCL.
+    #[derive(Default)]
+    struct Bus {
+        events: RefCell<Vec<&'static str>>,
+        scl_rises: Cell<u8>,
+        stuck_for: u8,
+    }
+
+    struct Scl<'a>(&'a Bus);
+    struct Sda<'a>(&'a Bus);
+
+    impl OutputPin for Scl<'_> {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            self.0.events.borrow_mut().push("scl low");
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            self.0.events.borrow_mut().push("scl high");
+            self.0.scl_rises.set(self.0.scl_rises.get() + 1);
+            Ok(())
+        }
+    }
+
+    impl InputPin for Sda<'_> {
+        type Error = Infallible;
+
+        fn is_high(&self) -> Result<bool, Infallible> {
+            Ok(self.0.scl_rises.get() >= self.0.stuck_for)
+        }
+
+        fn is_low(&self) -> Result<bool, Infallible> {
+            self.is_high().map(|high| !high)
+        }
+    }
+
+    impl OutputPin for Sda<'_> {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            self.0.events.borrow_mut().push("sda low");
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            self.0.events.borrow_mut().push("sda high");
+            Ok(())
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayUs<u16> for NoDelay {
+        fn delay_us(&mut self, _us: u16) {}
+    }
+
+    #[test]
+    fn counter_trips_after_threshold() {
+        let mut counter = FailureCounter::new(3);
+        assert!(!counter.record(false));
+        assert!(!counter.record(false));
+        assert!(counter.record(false));
+        // and starts over after tripping
+        assert_eq!(counter.consecutive(), 0);
+        assert!(!counter.record(false));
+    }
+
+    #[test]
+    fn success_resets_counter() {
+        let mut counter = FailureCounter::new(3);
+        counter.record(false);
+        counter.record(false);
+        assert!(!counter.record(true));
+        assert!(!counter.record(false));
+        assert!(!counter.record(false));
+        assert!(counter.record(false));
+    }
+
+    #[test]
+    fn clock_out_stops_once_sda_is_released() {
+        let bus = Bus { stuck_for: 4, ..Default::default() };
+        assert!(clock_out(&mut Scl(&bus), &Sda(&bus), &mut NoDelay));
+        assert_eq!(bus.scl_rises.get(), 4);
+    }
+
+    #[test]
+    fn clock_out_gives_up_after_nine_pulses() {
+        let bus = Bus { stuck_for: 20, ..Default::default() };
+        assert!(!clock_out(&mut Scl(&bus), &Sda(&bus), &mut NoDelay));
+        assert_eq!(bus.scl_rises.get(), RECOVERY_PULSES);
+    }
+
+    #[test]
+    fn free_bus_needs_no_pulses() {
+        let bus = Bus::default();
+        assert!(clock_out(&mut Scl(&bus), &Sda(&bus), &mut NoDelay));
+        assert!(bus.events.borrow().is_empty());
+    }
+
+    #[test]
+    fn stop_raises_sda_while_scl_is_high() {
+        let bus = Bus::default();
+        send_stop(&mut Scl(&bus), &mut Sda(&bus), &mut NoDelay);
+        assert_eq!(*bus.events.borrow(), ["scl low", "sda low", "scl high", "sda high"]);
+    }
+}
@@ -0,0 +1,177 @@
+use core::fmt::Write;
+use core::ops::RangeInclusive;
+
+use embedded_hal::blocking::i2c::Read;
+
+use crate::display::{LcdLine, LCD_COLUMNS};
+
+// 7-bit addresses a device can use; 0x00-0x07 and 0x78-0x7F are reserved
+pub const SCAN_RANGE: RangeInclusive<u8> = 0x08..=0x77;
+
+// PCF8574 LCD backpacks sit at 0x20-0x27, the PCF8574A variant at 0x38-0x3F
+// (set by the A0-A2 jumpers). The plain PCF8574 range is tried first.
+pub const LCD_ADDRESS_RANGES: [RangeInclusive<u8>; 2] = [0x20..=0x27, 0x38..=0x3F];
+
+// The DHT20 has a single fixed address
+pub const DHT20_ADDRESS: u8 = 0x38;
+
+// Fallback when no LCD answers (the address this board was built with)
+pub const DEFAULT_LCD_ADDRESS: u8 = 0x27;
+
+// The set of addresses that answered a scan, one bit per 7-bit address
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AddressSet {
+    bits: u128,
+}
+
+impl AddressSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, address: u8) {
+        if address < 0x80 {
+            self.bits |= 1 << address;
+        }
+    }
+
+    pub fn remove(&mut self, address: u8) {
+        if address < 0x80 {
+            self.bits &= !(1 << address);
+        }
+    }
+
+    pub fn contains(&self, address: u8) -> bool {
+        address < 0x80 && self.bits & (1 << address) != 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    // The addresses in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..0x80).filter(|address| self.contains(*address))
+    }
+}
+
+// Probe every address in SCAN_RANGE with a one byte read and collect the
+// ones that ACK. A read is used rather than an empty write because the
+// RP2040 I2C block can't send a zero length write, and reading a byte is
+// harmless for both the DHT20 (status byte) and the LCD backpack (port state).
+pub fn scan<I: Read>(i2c: &mut I) -> AddressSet {
+    let mut found = AddressSet::new();
+    for address in SCAN_RANGE {
+        if i2c.read(address, &mut [0]).is_ok() {
+            found.insert(address);
+        }
+    }
+    found
+}
+
+// The first address found in the LCD backpack ranges
+pub fn find_lcd(found: &AddressSet) -> Option<u8> {
+    LCD_ADDRESS_RANGES
+        .iter()
+        .flat_map(|range| range.clone())
+        .find(|address| found.contains(*address))
+}
+
+pub fn find_dht20(found: &AddressSet) -> Option<u8> {
+    Some(DHT20_ADDRESS).filter(|address| found.contains(*address))
+}
+
+// List the addresses on one LCD row, e.g. "0x27 0x38" (or "none"). Only the
+// first three fit; the count is shown elsewhere.
+pub fn address_line(found: &AddressSet) -> LcdLine {
+    let mut line = LcdLine::new();
+    if found.is_empty() {
+        let _ = line.write_str("none");
+    }
+    for (i, address) in found.iter().enumerate() {
+        let separator = if i == 0 { "" } else { " " };
+        if line.as_str().len() + separator.len() + 4 > LCD_COLUMNS {
+            break;
+        }
+        let _ = write!(line, "{separator}0x{address:02X}");
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bus double where only the given addresses ACK
+    struct MockBus {
+        present: &'static [u8],
+        probed: Vec<u8>,
+    }
+
+    impl Read for MockBus {
+        type Error = ();
+
+        fn read(&mut self, address: u8, _buffer: &mut [u8]) -> Result<(), ()> {
+            self.probed.push(address);
+            if self.present.contains(&address) {
+                Ok(())
+            } else {
+                Err(())
+            }
+        }
+    }
+
+    fn found(addresses: &[u8]) -> AddressSet {
+        let mut set = AddressSet::new();
+        for address in addresses {
+            set.insert(*address);
+        }
+        set
+    }
+
+    #[test]
+    fn scan_finds_responding_devices() {
+        let mut bus = MockBus { present: &[0x27, 0x38], probed: Vec::new() };
+        let found = scan(&mut bus);
+        assert_eq!(found.iter().collect::<Vec<_>>(), [0x27, 0x38]);
+        assert_eq!(found.len(), 2);
+        // reserved addresses are left alone
+        assert_eq!(bus.probed.first(), Some(&0x08));
+        assert_eq!(bus.probed.last(), Some(&0x77));
+    }
+
+    #[test]
+    fn lcd_found_in_either_range() {
+        assert_eq!(find_lcd(&found(&[0x27])), Some(0x27));
+        assert_eq!(find_lcd(&found(&[0x3F])), Some(0x3F));
+        assert_eq!(find_lcd(&found(&[0x21, 0x3F])), Some(0x21));
+        assert_eq!(find_lcd(&found(&[0x50])), None);
+    }
+
+    #[test]
+    fn removed_address_is_skipped() {
+        let mut set = found(&[0x27, 0x38]);
+        set.remove(0x27);
+        assert_eq!(find_lcd(&set), Some(0x38));
+        set.remove(0x38);
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn dht20_found_at_fixed_address() {
+        assert_eq!(find_dht20(&found(&[0x38])), Some(0x38));
+        assert_eq!(find_dht20(&found(&[0x39])), None);
+    }
+
+    #[test]
+    fn address_line_lists_addresses() {
+        assert_eq!(address_line(&found(&[])).as_str(), "none");
+        assert_eq!(address_line(&found(&[0x3F, 0x27])).as_str(), "0x27 0x3F");
+        // a fourth address doesn't fit on a 16 character row
+        assert_eq!(address_line(&found(&[0x20, 0x21, 0x22, 0x23])).as_str(), "0x20 0x21 0x22");
+    }
+}
@@ -0,0 +1,757 @@
+use embedded_hal::digital::v2::{OutputPin, PinState};
+use embedded_hal::PwmPin;
+
+use crate::time::Duration;
+
+// Humidity cut-offs for an N LED bar graph (five by default), lowest band
+// first. An LED is lit once the humidity reading is above its limit, so on the
+// five LED board the middle (green) LED being the highest one lit means the
+// humidity is inside the target band.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LedThresholds<const N: usize = 5> {
+    limits: [f32; N],
+}
+
+// Reasons a set of limits is rejected by LedThresholds::new
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThresholdError {
+    // A limit is NaN or outside 0-100 %
+    OutOfRange,
+    // The limits are not strictly increasing
+    NotAscending,
+}
+
+impl<const N: usize> LedThresholds<N> {
+    // Create a set of thresholds from N strictly ascending limits in %RH
+    pub fn new(limits: [f32; N]) -> Result<Self, ThresholdError> {
+        if limits.iter().any(|limit| !(0.0..=100.0).contains(limit)) {
+            return Err(ThresholdError::OutOfRange);
+        }
+        if limits.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(ThresholdError::NotAscending);
+        }
+        Ok(LedThresholds { limits })
+    }
+
+    // N equal bands starting at 0 %RH (0/20/40/60/80 for five LEDs)
+    pub fn evenly_spaced() -> Self {
+        LedThresholds {
+            limits: core::array::from_fn(|i| (i * 100) as f32 / N as f32),
+        }
+    }
+
+    pub fn limits(&self) -> &[f32; N] {
+        &self.limits
+    }
+
+    // Returns the number of LEDs that should be lit for the given humidity reading
+    pub fn level(&self, humidity: f32) -> usize {
+        self.limits
+            .iter()
+            .filter(|limit| humidity > **limit)
+            .count()
+    }
+}
+
+// Presets for the five LED bar graph
+impl LedThresholds<5> {
+    // Indoor living spaces: green between 40 and 60 %RH
+    pub fn comfort() -> Self {
+        LedThresholds {
+            limits: [0.0, 30.0, 40.0, 60.0, 70.0],
+        }
+    }
+
+    // Greenhouses: green between 60 and 80 %RH
+    pub fn greenhouse() -> Self {
+        LedThresholds {
+            limits: [0.0, 50.0, 60.0, 80.0, 90.0],
+        }
+    }
+
+    // Archive and museum storage: green between 30 and 50 %RH
+    pub fn archive_storage() -> Self {
+        LedThresholds {
+            limits: [0.0, 20.0, 30.0, 50.0, 60.0],
+        }
+    }
+}
+
+// The original evenly spaced bands (0/20/40/60/80 %RH for five LEDs)
+impl<const N: usize> Default for LedThresholds<N> {
+    fn default() -> Self {
+        Self::evenly_spaced()
+    }
+}
+
+// Default margin (in %RH) a reading must move past a threshold before the
+// level changes
+pub const DEFAULT_HYSTERESIS: f32 = 1.0;
+
+// Tracks the current level of a threshold-driven output (e.g. the LED bar
+// graph) and applies hysteresis, so a reading hovering right at a threshold
+// doesn't make the output flicker. The level only goes up once the reading is
+// more than `hysteresis` above the next threshold, and only goes down once it
+// is `hysteresis` or more below the current one (or at 0 %RH, for a threshold
+// closer to 0 than that, so the bottom band can still be left).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LevelTracker<const N: usize = 5> {
+    thresholds: LedThresholds<N>,
+    hysteresis: f32,
+    // None until the first reading arrives
+    level: Option<usize>,
+}
+
+impl<const N: usize> LevelTracker<N> {
+    pub fn new(thresholds: LedThresholds<N>, hysteresis: f32) -> Self {
+        LevelTracker {
+            thresholds,
+            hysteresis: hysteresis.max(0.0),
+            level: None,
+        }
+    }
+
+    pub fn thresholds(&self) -> &LedThresholds<N> {
+        &self.thresholds
+    }
+
+    // Current level (0 until the first update)
+    pub fn level(&self) -> usize {
+        self.level.unwrap_or(0)
+    }
+
+    // Change the thresholds; the next reading sets the level from scratch
+    pub fn set_thresholds(&mut self, thresholds: LedThresholds<N>) {
+        self.thresholds = thresholds;
+        self.level = None;
+    }
+
+    pub fn set_hysteresis(&mut self, hysteresis: f32) {
+        self.hysteresis = hysteresis.max(0.0);
+    }
+
+    // Feed a new humidity reading and return the resulting level
+    pub fn update(&mut self, humidity: f32) -> usize {
+        let limits = self.thresholds.limits();
+        let mut level = match self.level {
+            // the first reading has nothing to be sticky about
+            None => self.thresholds.level(humidity),
+            Some(level) => level,
+        };
+
+        while level < limits.len() && humidity > limits[level] + self.hysteresis {
+            level += 1;
+        }
+        while level > 0 && humidity <= (limits[level - 1] - self.hysteresis).max(0.0) {
+            level -= 1;
+        }
+
+        self.level = Some(level);
+        level
+    }
+}
+
+// Full brightness for a single LED or the whole array
+pub const MAX_LEVEL: u8 = 255;
+
+// Something that can show an LED at a brightness from 0 (off) to MAX_LEVEL.
+// Plain output pins get this for free and light up for any non-zero level,
+// which keeps on/off wiring working as a fallback when PWM isn't available.
+pub trait LedOutput {
+    fn set_level(&mut self, level: u8);
+}
+
+impl<P> LedOutput for P
+where
+    P: OutputPin,
+    P::Error: core::fmt::Debug,
+{
+    fn set_level(&mut self, level: u8) {
+        self.set_state(PinState::from(level > 0)).unwrap();
+    }
+}
+
+// An LED driven by a PWM channel (e.g. one of the RP2040 PWM slices), so it
+// can be dimmed. The level is squared before being turned into a duty cycle,
+// a rough gamma correction so the low levels used at night still look evenly
+// spaced to the eye.
+pub struct PwmLed<P> {
+    channel: P,
+}
+
+impl<P> PwmLed<P>
+where
+    P: PwmPin<Duty = u16>,
+{
+    // Takes a PWM channel that is already routed to the LED's GPIO, starting
+    // with the LED off
+    pub fn new(mut channel: P) -> Self {
+        channel.set_duty(0);
+        channel.enable();
+        PwmLed { channel }
+    }
+
+    // Give the channel back, e.g. to reconfigure the slice
+    pub fn release(self) -> P {
+        self.channel
+    }
+}
+
+impl<P> LedOutput for PwmLed<P>
+where
+    P: PwmPin<Duty = u16>,
+{
+    fn set_level(&mut self, level: u8) {
+        let max = MAX_LEVEL as u32;
+        let duty = self.channel.get_max_duty() as u32 * (level as u32 * level as u32) / (max * max);
+        self.channel.set_duty(duty as u16);
+    }
+}
+
+// A bar graph of N LEDs on any LED outputs. LED 0 is the bottom of the bar
+// (lit for the lowest band) and LED N-1 the top. Outputs with different
+// concrete types can be combined by converting them to one type first (e.g.
+// rp2040-hal's into_dyn_pin()).
+//
+// On dimmable outputs (PwmLed) the array also has a global brightness, a
+// per-LED level and can fade between humidity bands. Fades are stepped by
+// tick(), so with the default fade time of zero every change shows straight
+// away and plain on/off pins behave exactly as before.
+pub struct LedArray<P, const N: usize>
+where
+    P: LedOutput,
+{
+    pins: [P; N],
+    // humidity limits at which each LED turns on, and the level currently shown
+    levels: LevelTracker<N>,
+    // number of LEDs that should be lit (0 after a clear)
+    lit: usize,
+    // brightness applied on top of every LED's own level
+    brightness: u8,
+    // level of each LED when lit
+    led_levels: [u8; N],
+    // level each LED is currently shown at (before global brightness), and
+    // the level it is fading towards
+    shown: [f32; N],
+    target: [u8; N],
+    // time a fade from off to full takes
+    fade: Duration,
+}
+
+impl<P, const N: usize> LedArray<P, N>
+where
+    P: LedOutput,
+{
+    // Creates a new LedArray from the given pins (bottom of the bar first),
+    // the humidity thresholds that decide how many of them light up and the
+    // hysteresis margin (in %RH) applied around those thresholds
+    pub fn new(pins: [P; N], thresholds: LedThresholds<N>, hysteresis: f32) -> Self {
+        LedArray {
+            pins,
+            levels: LevelTracker::new(thresholds, hysteresis),
+            lit: 0,
+            brightness: MAX_LEVEL,
+            led_levels: [MAX_LEVEL; N],
+            shown: [0.0; N],
+            target: [0; N],
+            fade: Duration::ZERO,
+        }
+    }
+
+    // Switch to a different set of humidity bands (e.g. a preset)
+    pub fn set_thresholds(&mut self, thresholds: LedThresholds<N>) {
+        self.levels.set_thresholds(thresholds);
+    }
+
+    // Change how far past a threshold a reading must move to change level
+    pub fn set_hysteresis(&mut self, hysteresis: f32) {
+        self.levels.set_hysteresis(hysteresis);
+    }
+
+    // Dim (or brighten) the whole array, e.g. for night time. Takes effect
+    // immediately rather than fading.
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+        self.show();
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    // Set how bright a single LED is when lit (e.g. to tone down the red
+    // LEDs). Out of range indexes are ignored. Fades like a level change.
+    pub fn set_led_level(&mut self, index: usize, level: u8) {
+        if let Some(led_level) = self.led_levels.get_mut(index) {
+            *led_level = level;
+            self.retarget();
+        }
+    }
+
+    // How long a fade from fully off to fully on takes. Zero (the default)
+    // switches LEDs straight to their new level.
+    pub fn set_fade_time(&mut self, fade: Duration) {
+        self.fade = fade;
+    }
+
+    // Turn off all leds
+    pub fn clear(&mut self) {
+        self.lit = 0;
+        self.target = [0; N];
+        self.shown = [0.0; N];
+        self.show();
+    }
+
+    // Update the LED array based on the humidity reading. Every LED is driven
+    // on or off, so the array doesn't need clearing between readings, and
+    // the level only changes once the reading clears the hysteresis margin.
+    // With a fade time set the change is shown gradually by tick().
+    pub fn update(&mut self, humidity: &f32) {
+        self.lit = self.levels.update(*humidity);
+        self.retarget();
+    }
+
+    // True while any LED is still fading towards its new level
+    pub fn is_fading(&self) -> bool {
+        self.shown
+            .iter()
+            .zip(self.target.iter())
+            .any(|(shown, target)| *shown != *target as f32)
+    }
+
+    // Move every LED towards its target level by the amount of fade that
+    // fits in `elapsed`. Call this regularly (every 10-20 ms gives a smooth
+    // fade) while is_fading() is true.
+    pub fn tick(&mut self, elapsed: Duration) {
+        if !self.is_fading() {
+            return;
+        }
+        let step = if self.fade.as_micros() == 0 {
+            MAX_LEVEL as f32
+        } else {
+            MAX_LEVEL as f32 * elapsed.as_micros() as f32 / self.fade.as_micros() as f32
+        };
+        for (shown, target) in self.shown.iter_mut().zip(self.target.iter()) {
+            let target = *target as f32;
+            *shown = if *shown < target {
+                (*shown + step).min(target)
+            } else {
+                (*shown - step).max(target)
+            };
+        }
+        self.show();
+    }
+
+    // Light only LED `index` (when `lit`), e.g. to blink a fault code on the
+    // bar while there is no reading to show. Bypasses the fade; the next
+    // update() or clear() brings back the humidity level.
+    pub fn indicate(&mut self, index: usize, lit: bool) {
+        for (i, (shown, target)) in self.shown.iter_mut().zip(self.target.iter_mut()).enumerate() {
+            *target = if lit && i == index { MAX_LEVEL } else { 0 };
+            *shown = *target as f32;
+        }
+        self.show();
+    }
+
+    // Give the pins back, e.g. to reconfigure them for another function
+    pub fn release(self) -> [P; N] {
+        self.pins
+    }
+
+    // Work out the level each LED should end up at, jumping straight there
+    // when fading is off
+    fn retarget(&mut self) {
+        for (i, target) in self.target.iter_mut().enumerate() {
+            *target = if i < self.lit { self.led_levels[i] } else { 0 };
+        }
+        if self.fade.as_micros() == 0 {
+            self.shown = self.target.map(|target| target as f32);
+        }
+        self.show();
+    }
+
+    // Drive every output at its shown level scaled by the global brightness
+    fn show(&mut self) {
+        for (pin, shown) in self.pins.iter_mut().zip(self.shown.iter()) {
+            let level = libm::roundf(*shown) as u32 * self.brightness as u32 / MAX_LEVEL as u32;
+            pin.set_level(level as u8);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    // Check the number of LEDs lit for each (humidity, level) pair
+    fn assert_levels(thresholds: LedThresholds, expected: &[(f32, usize)]) {
+        for (humidity, level) in expected {
+            assert_eq!(thresholds.level(*humidity), *level, "humidity {humidity}");
+        }
+    }
+
+    #[test]
+    fn default_lights_one_led_per_twenty_percent() {
+        assert_levels(
+            LedThresholds::default(),
+            &[(-5.0, 0), (0.0, 0), (10.0, 1), (30.0, 2), (50.0, 3), (70.0, 4), (90.0, 5)],
+        );
+    }
+
+    #[test]
+    fn level_is_exclusive_at_band_edges() {
+        assert_levels(LedThresholds::default(), &[(20.0, 1), (40.0, 2), (80.0, 4)]);
+    }
+
+    #[test]
+    fn comfort_preset_levels() {
+        assert_levels(
+            LedThresholds::comfort(),
+            &[(10.0, 1), (35.0, 2), (50.0, 3), (65.0, 4), (75.0, 5)],
+        );
+    }
+
+    #[test]
+    fn greenhouse_preset_levels() {
+        assert_levels(
+            LedThresholds::greenhouse(),
+            &[(30.0, 1), (55.0, 2), (70.0, 3), (85.0, 4), (95.0, 5)],
+        );
+    }
+
+    #[test]
+    fn archive_storage_preset_levels() {
+        assert_levels(
+            LedThresholds::archive_storage(),
+            &[(10.0, 1), (25.0, 2), (40.0, 3), (55.0, 4), (65.0, 5)],
+        );
+    }
+
+    #[test]
+    fn presets_pass_validation() {
+        for preset in [
+            LedThresholds::default(),
+            LedThresholds::comfort(),
+            LedThresholds::greenhouse(),
+            LedThresholds::archive_storage(),
+        ] {
+            assert_eq!(LedThresholds::new(*preset.limits()), Ok(preset));
+        }
+    }
+
+    #[test]
+    fn new_rejects_invalid_limits() {
+        assert_eq!(
+            LedThresholds::new([0.0, 20.0, 20.0, 60.0, 80.0]),
+            Err(ThresholdError::NotAscending)
+        );
+        assert_eq!(
+            LedThresholds::new([0.0, 40.0, 20.0, 60.0, 80.0]),
+            Err(ThresholdError::NotAscending)
+        );
+        assert_eq!(
+            LedThresholds::new([-1.0, 20.0, 40.0, 60.0, 80.0]),
+            Err(ThresholdError::OutOfRange)
+        );
+        assert_eq!(
+            LedThresholds::new([0.0, 20.0, 40.0, 60.0, 101.0]),
+            Err(ThresholdError::OutOfRange)
+        );
+        assert_eq!(
+            LedThresholds::new([0.0, f32::NAN, 40.0, 60.0, 80.0]),
+            Err(ThresholdError::OutOfRange)
+        );
+    }
+
+    #[test]
+    fn tracker_starts_at_raw_level() {
+        let mut tracker: LevelTracker = LevelTracker::new(LedThresholds::default(), DEFAULT_HYSTERESIS);
+        assert_eq!(tracker.level(), 0);
+        assert_eq!(tracker.update(40.5), 3);
+    }
+
+    #[test]
+    fn tracker_holds_level_near_threshold() {
+        let mut tracker: LevelTracker = LevelTracker::new(LedThresholds::default(), 1.0);
+        assert_eq!(tracker.update(39.0), 2);
+
+        // hovering around 40 % never reaches 41 %, so the green LED stays off
+        for humidity in [40.0, 40.5, 39.8, 40.9, 40.0] {
+            assert_eq!(tracker.update(humidity), 2, "humidity {humidity}");
+        }
+        assert_eq!(tracker.update(41.1), 3);
+
+        // and coming back down it stays on until 39 %
+        for humidity in [40.0, 39.5, 40.2, 39.1] {
+            assert_eq!(tracker.update(humidity), 3, "humidity {humidity}");
+        }
+        assert_eq!(tracker.update(39.0), 2);
+    }
+
+    #[test]
+    fn tracker_jumps_several_levels_at_once() {
+        let mut tracker: LevelTracker = LevelTracker::new(LedThresholds::default(), 1.0);
+        assert_eq!(tracker.update(10.0), 1);
+        assert_eq!(tracker.update(95.0), 5);
+        assert_eq!(tracker.update(5.0), 1);
+    }
+
+    #[test]
+    fn tracker_drops_from_the_bottom_band() {
+        // every preset starts its bottom band at 0 %RH
+        let mut tracker: LevelTracker = LevelTracker::new(LedThresholds::comfort(), 1.0);
+        assert_eq!(tracker.update(10.0), 1);
+        assert_eq!(tracker.update(0.5), 1);
+        assert_eq!(tracker.update(0.0), 0);
+        assert_eq!(tracker.update(0.5), 0);
+        assert_eq!(tracker.update(1.1), 1);
+    }
+
+    #[test]
+    fn zero_hysteresis_matches_raw_level() {
+        let mut tracker: LevelTracker = LevelTracker::new(LedThresholds::default(), 0.0);
+        for humidity in [10.0, 20.0, 20.1, 40.0, 39.9, 80.0, 80.1, 0.0] {
+            assert_eq!(
+                tracker.update(humidity),
+                LedThresholds::<5>::default().level(humidity),
+                "humidity {humidity}"
+            );
+        }
+    }
+
+    #[test]
+    fn changing_thresholds_resets_level() {
+        let mut tracker: LevelTracker = LevelTracker::new(LedThresholds::default(), 1.0);
+        assert_eq!(tracker.update(50.0), 3);
+        tracker.set_thresholds(LedThresholds::greenhouse());
+        assert_eq!(tracker.level(), 0);
+        assert_eq!(tracker.update(50.0), 1);
+    }
+
+    #[test]
+    fn evenly_spaced_supports_any_led_count() {
+        assert_eq!(LedThresholds::<5>::evenly_spaced().limits(), &[0.0, 20.0, 40.0, 60.0, 80.0]);
+        assert_eq!(LedThresholds::<4>::evenly_spaced().limits(), &[0.0, 25.0, 50.0, 75.0]);
+        assert_eq!(LedThresholds::<2>::evenly_spaced().limits(), &[0.0, 50.0]);
+    }
+
+    // Output pin double backed by a shared cell so tests can see its state
+    struct MockPin<'a> {
+        lit: &'a Cell<bool>,
+    }
+
+    impl OutputPin for MockPin<'_> {
+        type Error = core::convert::Infallible;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.lit.set(false);
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.lit.set(true);
+            Ok(())
+        }
+    }
+
+    fn lit<const N: usize>(cells: &[Cell<bool>; N]) -> [bool; N] {
+        core::array::from_fn(|i| cells[i].get())
+    }
+
+    #[test]
+    fn led_array_lights_expected_leds() {
+        let cells: [Cell<bool>; 5] = Default::default();
+        let pins: [MockPin; 5] = core::array::from_fn(|i| MockPin { lit: &cells[i] });
+        let mut leds = LedArray::new(pins, LedThresholds::default(), 0.0);
+
+        leds.update(&10.0);
+        assert_eq!(lit(&cells), [true, false, false, false, false]);
+        leds.update(&50.0);
+        assert_eq!(lit(&cells), [true, true, true, false, false]);
+        leds.update(&90.0);
+        assert_eq!(lit(&cells), [true; 5]);
+        // going down turns the upper LEDs back off without a clear()
+        leds.update(&30.0);
+        assert_eq!(lit(&cells), [true, true, false, false, false]);
+
+        leds.clear();
+        assert_eq!(lit(&cells), [false; 5]);
+    }
+
+    #[test]
+    fn led_array_supports_other_led_counts() {
+        let cells: [Cell<bool>; 3] = Default::default();
+        let pins: [MockPin; 3] = core::array::from_fn(|i| MockPin { lit: &cells[i] });
+        let thresholds = LedThresholds::new([30.0, 50.0, 70.0]).unwrap();
+        let mut leds = LedArray::new(pins, thresholds, 0.0);
+
+        leds.update(&20.0);
+        assert_eq!(lit(&cells), [false; 3]);
+        leds.update(&60.0);
+        assert_eq!(lit(&cells), [true, true, false]);
+
+        let cells: [Cell<bool>; 8] = Default::default();
+        let pins: [MockPin; 8] = core::array::from_fn(|i| MockPin { lit: &cells[i] });
+        let mut leds = LedArray::new(pins, LedThresholds::default(), 0.0);
+
+        leds.update(&80.0);
+        assert_eq!(lit(&cells), [true, true, true, true, true, true, true, false]);
+    }
+
+    #[test]
+    fn led_array_applies_hysteresis() {
+        let cells: [Cell<bool>; 5] = Default::default();
+        let pins: [MockPin; 5] = core::array::from_fn(|i| MockPin { lit: &cells[i] });
+        let mut leds = LedArray::new(pins, LedThresholds::default(), DEFAULT_HYSTERESIS);
+
+        leds.update(&39.5);
+        for humidity in [40.0, 40.5, 39.9] {
+            leds.update(&humidity);
+            assert!(!cells[2].get(), "green LED lit at {humidity}");
+        }
+    }
+
+    // PWM channel double recording the last duty cycle set
+    struct MockPwm<'a> {
+        duty: &'a Cell<u16>,
+    }
+
+    impl PwmPin for MockPwm<'_> {
+        type Duty = u16;
+
+        fn disable(&mut self) {}
+
+        fn enable(&mut self) {}
+
+        fn get_duty(&self) -> u16 {
+            self.duty.get()
+        }
+
+        fn get_max_duty(&self) -> u16 {
+            u16::MAX
+        }
+
+        fn set_duty(&mut self, duty: u16) {
+            self.duty.set(duty);
+        }
+    }
+
+    fn duties<const N: usize>(cells: &[Cell<u16>; N]) -> [u16; N] {
+        core::array::from_fn(|i| cells[i].get())
+    }
+
+    #[test]
+    fn pwm_led_is_gamma_corrected() {
+        let duty = Cell::new(1);
+        let mut led = PwmLed::new(MockPwm { duty: &duty });
+        assert_eq!(duty.get(), 0);
+
+        led.set_level(MAX_LEVEL);
+        assert_eq!(duty.get(), u16::MAX);
+        // half level gives roughly a quarter duty cycle
+        led.set_level(128);
+        assert_eq!(duty.get(), 16512);
+        led.set_level(0);
+        assert_eq!(duty.get(), 0);
+    }
+
+    #[test]
+    fn brightness_and_led_levels_scale_output() {
+        let cells: [Cell<u16>; 5] = Default::default();
+        let pins: [PwmLed<MockPwm>; 5] = core::array::from_fn(|i| PwmLed::new(MockPwm { duty: &cells[i] }));
+        let mut leds = LedArray::new(pins, LedThresholds::default(), 0.0);
+
+        leds.update(&50.0);
+        assert_eq!(duties(&cells), [u16::MAX, u16::MAX, u16::MAX, 0, 0]);
+
+        leds.set_led_level(0, 128);
+        assert_eq!(duties(&cells), [16512, u16::MAX, u16::MAX, 0, 0]);
+
+        leds.set_brightness(128);
+        assert_eq!(duties(&cells), [4128, 16512, 16512, 0, 0]);
+
+        leds.set_brightness(0);
+        assert_eq!(duties(&cells), [0; 5]);
+    }
+
+    #[test]
+    fn fades_between_bands() {
+        let cells: [Cell<u16>; 5] = Default::default();
+        let pins: [PwmLed<MockPwm>; 5] = core::array::from_fn(|i| PwmLed::new(MockPwm { duty: &cells[i] }));
+        let mut leds = LedArray::new(pins, LedThresholds::default(), 0.0);
+        leds.set_fade_time(Duration::from_millis(400));
+
+        leds.update(&30.0);
+        assert!(leds.is_fading());
+        assert_eq!(duties(&cells), [0; 5]);
+
+        // halfway through the fade both LEDs are at half level
+        leds.tick(Duration::from_millis(200));
+        let half = duties(&cells);
+        assert!(half[0] > 0 && half[0] < u16::MAX);
+        assert_eq!(half[0], half[1]);
+
+        leds.tick(Duration::from_millis(200));
+        assert!(!leds.is_fading());
+        assert_eq!(duties(&cells), [u16::MAX, u16::MAX, 0, 0, 0]);
+
+        // fading down from two LEDs to one
+        leds.update(&10.0);
+        leds.tick(Duration::from_millis(100));
+        assert_eq!(cells[0].get(), u16::MAX);
+        assert!(cells[1].get() > 0 && cells[1].get() < u16::MAX);
+        leds.tick(Duration::from_secs(1));
+        assert_eq!(duties(&cells), [u16::MAX, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn clear_skips_the_fade() {
+        let cells: [Cell<u16>; 5] = Default::default();
+        let pins: [PwmLed<MockPwm>; 5] = core::array::from_fn(|i| PwmLed::new(MockPwm { duty: &cells[i] }));
+        let mut leds = LedArray::new(pins, LedThresholds::default(), 0.0);
+        leds.update(&90.0);
+        leds.set_fade_time(Duration::from_millis(400));
+
+        leds.clear();
+        assert!(!leds.is_fading());
+        assert_eq!(duties(&cells), [0; 5]);
+    }
+
+    #[test]
+    fn indicate_lights_a_single_led() {
+        let cells: [Cell<bool>; 5] = Default::default();
+        let pins: [MockPin; 5] = core::array::from_fn(|i| MockPin { lit: &cells[i] });
+        let mut leds = LedArray::new(pins, LedThresholds::default(), 0.0);
+        leds.update(&50.0);
+
+        leds.indicate(3, true);
+        assert_eq!(lit(&cells), [false, false, false, true, false]);
+        leds.indicate(3, false);
+        assert_eq!(lit(&cells), [false; 5]);
+
+        leds.update(&50.0);
+        assert_eq!(lit(&cells), [true, true, true, false, false]);
+    }
+
+    #[test]
+    fn on_off_pins_follow_the_fade_target() {
+        let cells: [Cell<bool>; 5] = Default::default();
+        let pins: [MockPin; 5] = core::array::from_fn(|i| MockPin { lit: &cells[i] });
+        let mut leds = LedArray::new(pins, LedThresholds::default(), 0.0);
+        leds.set_fade_time(Duration::from_millis(400));
+
+        // a plain pin comes on as soon as a fade up starts...
+        leds.update(&30.0);
+        leds.tick(Duration::from_millis(10));
+        assert_eq!(lit(&cells), [true, true, false, false, false]);
+        leds.tick(Duration::from_millis(400));
+
+        // ...and goes off once a fade down finishes
+        leds.update(&10.0);
+        leds.tick(Duration::from_millis(200));
+        assert_eq!(lit(&cells), [true, true, false, false, false]);
+        leds.tick(Duration::from_millis(200));
+        assert_eq!(lit(&cells), [true, false, false, false, false]);
+    }
+}
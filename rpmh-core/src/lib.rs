@@ -0,0 +1,28 @@
+// Compile without standard library (the standard library is only pulled in
+// for `cargo test` on the host)
+#![cfg_attr(not(test), no_std)]
+
+// Everything in this crate is hardware-independent: conversions, formatting,
+// thresholds and drivers written against the embedded-hal traits. The RP2040
+// specific wiring lives in the OSU-RPMH crate, which re-exports these modules.
+pub mod blink;
+pub mod data_log;
+pub mod dht;
+pub mod display;
+pub mod export;
+pub mod hd44780;
+pub mod i2c_recovery;
+pub mod i2c_scan;
+pub mod leds;
+pub mod log_ring;
+pub mod psychrometrics;
+#[cfg(test)]
+mod ram_flash;
+pub mod report;
+pub mod scheduler;
+pub mod settings;
+pub mod settings_store;
+pub mod shared_i2c;
+pub mod shell;
+pub mod time;
+pub mod utils;
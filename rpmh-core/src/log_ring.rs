@@ -0,0 +1,131 @@
+use core::fmt::{self, Write};
+
+use heapless::{Deque, String};
+use log::Level;
+
+// The most recent log messages, kept in RAM so they can be read back over
+// the serial console (the firmware's logger writes into one of these, and
+// the status command prints it). Nothing is written anywhere else, so the
+// boot messages (the reset reason, which bus the LCD turned up on, ...) are
+// still there once a terminal is connected.
+
+// Longest message kept, including its level; longer ones are cut short
+pub const LINE_LEN: usize = 80;
+
+// Holds the last N messages, oldest first. Once full, each new message
+// pushes out the oldest.
+pub struct LogRing<const N: usize> {
+    lines: Deque<String<LINE_LEN>, N>,
+    // messages pushed out since boot, so a reader knows some are missing
+    dropped: u32,
+}
+
+impl<const N: usize> LogRing<N> {
+    pub const fn new() -> Self {
+        LogRing {
+            lines: Deque::new(),
+            dropped: 0,
+        }
+    }
+
+    // Keep a message as "LEVEL message", e.g. "WARN  no saved settings, ..."
+    pub fn push(&mut self, level: Level, message: &fmt::Arguments) {
+        let mut line = Line(String::new());
+        let _ = write!(line, "{:<5} {}", level, message);
+        if self.lines.is_full() {
+            self.lines.pop_front();
+            self.dropped = self.dropped.saturating_add(1);
+        }
+        let _ = self.lines.push_back(line.0);
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    // One message per line, oldest first, after a note of how many were
+    // dropped (if any)
+    pub fn write_to<W: Write>(&self, out: &mut W) -> fmt::Result {
+        if self.dropped > 0 {
+            write!(out, "({} earlier messages dropped)\r\n", self.dropped)?;
+        }
+        for line in &self.lines {
+            out.write_str(line)?;
+            out.write_str("\r\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl<const N: usize> Default for LogRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A message being formatted, cut short (at a character boundary) rather than
+// lost when it doesn't fit
+struct Line(String<LINE_LEN>);
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.0.push(c).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written<const N: usize>(ring: &LogRing<N>) -> std::string::String {
+        let mut out = std::string::String::new();
+        ring.write_to(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn keeps_messages_with_their_level() {
+        let mut ring = LogRing::<4>::new();
+        assert!(ring.is_empty());
+        ring.push(Level::Info, &format_args!("Board {}", "Rev 1"));
+        ring.push(Level::Warn, &format_args!("no LCD found on I2C{}", 0));
+
+        assert_eq!(ring.len(), 2);
+        assert_eq!(written(&ring), "INFO  Board Rev 1\r\nWARN  no LCD found on I2C0\r\n");
+    }
+
+    #[test]
+    fn oldest_messages_make_room_for_new_ones() {
+        let mut ring = LogRing::<2>::new();
+        for n in 0..5 {
+            ring.push(Level::Info, &format_args!("message {}", n));
+        }
+
+        assert_eq!(ring.len(), 2);
+        assert_eq!(
+            written(&ring),
+            "(3 earlier messages dropped)\r\nINFO  message 3\r\nINFO  message 4\r\n"
+        );
+    }
+
+    #[test]
+    fn long_messages_are_cut_short() {
+        let mut ring = LogRing::<1>::new();
+        let long = "é".repeat(LINE_LEN);
+        ring.push(Level::Error, &format_args!("{}", long));
+
+        let out = written(&ring);
+        let line = out.strip_suffix("\r\n").unwrap();
+        assert!(line.starts_with("ERROR é"));
+        assert!(line.len() <= LINE_LEN && line.len() > LINE_LEN - 2);
+    }
+}
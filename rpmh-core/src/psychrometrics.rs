@@ -0,0 +1,137 @@
+/*
+*  psychrometrics.rs derives comfort and moisture metrics from a DHT20
+*  reading. All temperatures are in degrees Celsius and relative humidity is
+*  in percent, matching dht::Reading. The exp/ln calls come from libm since
+*  the float methods normally used for them are part of the standard library.
+*/
+
+use libm::{expf, fabsf, logf, sqrtf};
+
+use crate::dht::Reading;
+
+// Magnus formula coefficients (Alduchov & Eskridge / Sonntag), valid from
+// -45 C to 60 C over water
+const MAGNUS_A: f32 = 17.62;
+const MAGNUS_B: f32 = 243.12;
+
+// Dew point in C using the Magnus formula
+pub fn dew_point(reading: &Reading) -> f32 {
+    // ln(0) is undefined, so treat a bone dry reading as the driest the
+    // sensor can actually report
+    let hum = reading.hum.max(0.01);
+    let gamma = logf(hum / 100.0) + MAGNUS_A * reading.temp / (MAGNUS_B + reading.temp);
+    MAGNUS_B * gamma / (MAGNUS_A - gamma)
+}
+
+// Absolute humidity (water vapour density) in g/m³
+pub fn absolute_humidity(reading: &Reading) -> f32 {
+    let temp = reading.temp;
+    // saturation vapour pressure in hPa, scaled by the relative humidity and
+    // converted to a density with the ideal gas law for water vapour
+    let saturation_hpa = 6.112 * expf(17.67 * temp / (temp + 243.5));
+    saturation_hpa * reading.hum * 2.1674 / (273.15 + temp)
+}
+
+// Heat index (apparent temperature) in C, using the US National Weather
+// Service's Rothfusz regression with its low and high humidity adjustments
+pub fn heat_index(reading: &Reading) -> f32 {
+    // the NWS equations are defined in Fahrenheit
+    let t = reading.temp * 1.8 + 32.0;
+    let rh = reading.hum;
+
+    // Steadman's simple formula is used when it gives less than 80 F
+    let simple = 0.5 * (t + 61.0 + (t - 68.0) * 1.2 + rh * 0.094);
+    let mut hi = (simple + t) / 2.0;
+
+    if hi >= 80.0 {
+        hi = -42.379 + 2.049_015 * t + 10.143_331 * rh
+            - 0.224_755_4 * t * rh
+            - 0.006_837_83 * t * t
+            - 0.054_817_17 * rh * rh
+            + 0.001_228_74 * t * t * rh
+            + 0.000_852_82 * t * rh * rh
+            - 0.000_001_99 * t * t * rh * rh;
+
+        if rh < 13.0 && (80.0..=112.0).contains(&t) {
+            hi -= ((13.0 - rh) / 4.0) * sqrtf((17.0 - fabsf(t - 95.0)) / 17.0);
+        } else if rh > 85.0 && (80.0..=87.0).contains(&t) {
+            hi += ((rh - 85.0) / 10.0) * ((87.0 - t) / 5.0);
+        }
+    }
+
+    (hi - 32.0) / 1.8
+}
+
+// Humidex as defined by Environment Canada (a dimensionless "feels like"
+// value on the Celsius scale)
+pub fn humidex(reading: &Reading) -> f32 {
+    humidex_from_dew_point(reading.temp, dew_point(reading))
+}
+
+fn humidex_from_dew_point(temp: f32, dew_point: f32) -> f32 {
+    // vapour pressure in hPa at the dew point
+    let vapour_pressure = 6.11 * expf(5417.753 * (1.0 / 273.16 - 1.0 / (273.15 + dew_point)));
+    temp + 0.5555 * (vapour_pressure - 10.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(temp: f32, hum: f32) -> Reading {
+        Reading { temp, hum }
+    }
+
+    fn fahrenheit(temp: f32) -> f32 {
+        (temp - 32.0) / 1.8
+    }
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            fabsf(actual - expected) <= tolerance,
+            "expected {expected} +/- {tolerance}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn dew_point_matches_reference_values() {
+        assert_close(dew_point(&reading(25.0, 50.0)), 13.9, 0.1);
+        assert_close(dew_point(&reading(30.0, 70.0)), 23.9, 0.1);
+        assert_close(dew_point(&reading(0.0, 80.0)), -3.0, 0.1);
+        // at saturation the dew point is the air temperature
+        assert_close(dew_point(&reading(20.0, 100.0)), 20.0, 0.01);
+    }
+
+    #[test]
+    fn dew_point_handles_zero_humidity() {
+        assert!(dew_point(&reading(20.0, 0.0)).is_finite());
+    }
+
+    #[test]
+    fn absolute_humidity_matches_reference_values() {
+        // saturation densities: 17.3 g/m³ at 20 C and 23.0 g/m³ at 25 C
+        assert_close(absolute_humidity(&reading(20.0, 100.0)), 17.3, 0.1);
+        assert_close(absolute_humidity(&reading(25.0, 50.0)), 11.5, 0.1);
+        assert_close(absolute_humidity(&reading(25.0, 0.0)), 0.0, 0.001);
+    }
+
+    #[test]
+    fn heat_index_matches_nws_table() {
+        assert_close(heat_index(&reading(fahrenheit(90.0), 50.0)), fahrenheit(95.0), 0.5);
+        assert_close(heat_index(&reading(fahrenheit(100.0), 40.0)), fahrenheit(109.0), 0.5);
+        assert_close(heat_index(&reading(fahrenheit(84.0), 90.0)), fahrenheit(98.0), 0.5);
+        assert_close(heat_index(&reading(fahrenheit(80.0), 40.0)), fahrenheit(80.0), 0.5);
+    }
+
+    #[test]
+    fn heat_index_uses_simple_formula_when_cool() {
+        assert_close(heat_index(&reading(20.0, 50.0)), 19.7, 0.1);
+    }
+
+    #[test]
+    fn humidex_matches_environment_canada_examples() {
+        assert_close(humidex_from_dew_point(30.0, 15.0), 34.0, 0.1);
+        assert_close(humidex_from_dew_point(35.0, 25.0), 47.3, 0.1);
+        assert_close(humidex(&reading(30.0, 50.0)), 36.3, 0.1);
+    }
+}
@@ -0,0 +1,82 @@
+use embedded_storage::nor_flash::{
+    check_erase, check_read, check_write, ErrorType, MultiwriteNorFlash, NorFlash,
+    NorFlashErrorKind, ReadNorFlash,
+};
+
+use crate::settings_store::SECTOR_SIZE;
+
+// Flash kept in RAM for the tests of the flash stores. Like NOR flash,
+// erasing sets a sector to 0xFF. By default a byte can only be programmed
+// once between erases, and programming it twice fails the test.
+pub struct RamFlash {
+    pub bytes: Vec<u8>,
+    // Offset of each sector erased, in order
+    pub erases: Vec<u32>,
+    // Whether programmed bytes can be programmed again (see multiwrite)
+    multiwrite: bool,
+}
+
+impl RamFlash {
+    pub fn new(sectors: u32) -> Self {
+        RamFlash {
+            bytes: vec![0xFF; (sectors * SECTOR_SIZE) as usize],
+            erases: Vec::new(),
+            multiwrite: false,
+        }
+    }
+
+    // Flash that can be programmed again without an erase, as the Pico's
+    // can: that only clears bits (what is written is ANDed with what is
+    // there), so anything relying on overwriting shows up as damaged data.
+    // For stores that rely on MultiwriteNorFlash, like the data log.
+    pub fn multiwrite(sectors: u32) -> Self {
+        RamFlash {
+            multiwrite: true,
+            ..RamFlash::new(sectors)
+        }
+    }
+}
+
+impl ErrorType for RamFlash {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for RamFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check_read(self, offset, bytes.len())?;
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.bytes[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl NorFlash for RamFlash {
+    // Same page and sector sizes as the Pico's flash chip
+    const WRITE_SIZE: usize = 256;
+    const ERASE_SIZE: usize = SECTOR_SIZE as usize;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        check_erase(self, from, to)?;
+        self.bytes[from as usize..to as usize].fill(0xFF);
+        self.erases.push(from);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_write(self, offset, bytes.len())?;
+        let target = &mut self.bytes[offset as usize..offset as usize + bytes.len()];
+        assert!(self.multiwrite || target.iter().all(|byte| *byte == 0xFF), "programmed twice");
+        for (old, new) in target.iter_mut().zip(bytes) {
+            *old &= new;
+        }
+        Ok(())
+    }
+}
+
+impl MultiwriteNorFlash for RamFlash {}
@@ -0,0 +1,71 @@
+use core::fmt::{self, Write};
+
+use crate::dht::Reading;
+use crate::display::TemperatureUnit;
+use crate::time::Instant;
+use crate::utils::round_to_decimal;
+
+// Lines of text streamed over the USB serial port, one per reading. Each
+// starts with the time since boot so a capture can be lined up afterwards,
+// and ends with CRLF as serial terminals expect.
+
+// Write the timestamp, e.g. "12.345 s", always with three decimals
+fn write_timestamp<W: Write>(out: &mut W, timestamp: Instant) -> fmt::Result {
+    let millis = timestamp.as_micros() / 1_000;
+    write!(out, "{}.{:03} s", millis / 1_000, millis % 1_000)
+}
+
+// e.g. "12.345 s  23.4 C  45.2 %RH"
+pub fn write_reading<W: Write>(
+    out: &mut W,
+    timestamp: Instant,
+    reading: &Reading,
+    unit: TemperatureUnit,
+    rounding: u32,
+) -> fmt::Result {
+    let mut buffer = ryu::Buffer::new();
+
+    write_timestamp(out, timestamp)?;
+    let temp = round_to_decimal(unit.from_celsius(reading.temp), rounding);
+    write!(out, "  {} {}", buffer.format(temp), unit.symbol())?;
+    let hum = round_to_decimal(reading.hum, rounding);
+    write!(out, "  {} %RH\r\n", buffer.format(hum))
+}
+
+// A failed reading, e.g. "12.345 s  error: Sensor NACK"
+pub fn write_error<W: Write>(out: &mut W, timestamp: Instant, description: &str) -> fmt::Result {
+    write_timestamp(out, timestamp)?;
+    write!(out, "  error: {}\r\n", description)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading() -> Reading {
+        Reading { temp: 23.44, hum: 45.16 }
+    }
+
+    #[test]
+    fn reading_line_is_timestamped() {
+        let mut out = String::new();
+        let timestamp = Instant::from_micros(12_345_678);
+        write_reading(&mut out, timestamp, &reading(), TemperatureUnit::Celsius, 1).unwrap();
+        assert_eq!(out, "12.345 s  23.4 C  45.2 %RH\r\n");
+    }
+
+    #[test]
+    fn reading_line_in_fahrenheit() {
+        let mut out = String::new();
+        write_reading(&mut out, Instant::from_micros(0), &reading(), TemperatureUnit::Fahrenheit, 1)
+            .unwrap();
+        assert_eq!(out, "0.000 s  74.2 F  45.2 %RH\r\n");
+    }
+
+    #[test]
+    fn error_line() {
+        let mut out = String::new();
+        write_error(&mut out, Instant::from_micros(61_005_000), "Sensor NACK").unwrap();
+        assert_eq!(out, "61.005 s  error: Sensor NACK\r\n");
+    }
+}
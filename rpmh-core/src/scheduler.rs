@@ -0,0 +1,183 @@
+use crate::time::{Duration, Instant};
+
+// A periodic task: an id chosen by the caller (usually a small enum), how
+// often it runs and when it is next due
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Task<T> {
+    id: T,
+    interval: Duration,
+    next: Instant,
+}
+
+// Cooperative scheduler for a fixed set of N periodic tasks. It doesn't run
+// anything itself: the main loop polls it with the current time and runs
+// whichever task comes back, so every task has to return quickly instead of
+// busy-waiting. Tasks with independent intervals can then share the loop
+// (e.g. sampling the sensor every 10 s while stepping LED fades every 20 ms).
+#[derive(Debug, Clone)]
+pub struct Scheduler<T, const N: usize> {
+    tasks: [Task<T>; N],
+}
+
+impl<T, const N: usize> Scheduler<T, N>
+where
+    T: Copy + PartialEq,
+{
+    // Create a scheduler from (task, interval) pairs. Every task is due
+    // straight away; use run_after to hold one back.
+    pub fn new(tasks: [(T, Duration); N], now: Instant) -> Self {
+        Scheduler {
+            tasks: tasks.map(|(id, interval)| Task { id, interval, next: now }),
+        }
+    }
+
+    // The task that should run now, if any. When several are due the most
+    // overdue one wins (ties go to the one listed first). Its next run is
+    // booked one interval later; a task that has fallen more than a whole
+    // interval behind is rebooked from `now` rather than run in a burst.
+    pub fn poll(&mut self, now: Instant) -> Option<T> {
+        let task = self
+            .tasks
+            .iter_mut()
+            .filter(|task| task.next <= now)
+            .min_by_key(|task| task.next)?;
+
+        task.next = task.next + task.interval;
+        if task.next <= now {
+            task.next = now + task.interval;
+        }
+        Some(task.id)
+    }
+
+    // Change how often a task runs, starting from its next run
+    pub fn set_interval(&mut self, id: T, interval: Duration) {
+        if let Some(task) = self.task_mut(id) {
+            task.interval = interval;
+        }
+    }
+
+    // Book a task's next run for `delay` from now, e.g. to show an LCD page
+    // for longer than usual or to give the sensor time to power up
+    pub fn run_after(&mut self, id: T, delay: Duration, now: Instant) {
+        if let Some(task) = self.task_mut(id) {
+            task.next = now + delay;
+        }
+    }
+
+    // Make a task due on the next poll
+    pub fn run_now(&mut self, id: T, now: Instant) {
+        self.run_after(id, Duration::ZERO, now);
+    }
+
+    // How long until the next task is due (zero if one is due already), so
+    // the caller knows how long it can sleep
+    pub fn time_until_next(&self, now: Instant) -> Duration {
+        self.tasks
+            .iter()
+            .map(|task| task.next.duration_since(now))
+            .min()
+            .unwrap_or(Duration::ZERO)
+    }
+
+    fn task_mut(&mut self, id: T) -> Option<&mut Task<T>> {
+        self.tasks.iter_mut().find(|task| task.id == id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Job {
+        Fast,
+        Slow,
+    }
+
+    fn at(ms: u64) -> Instant {
+        Instant::from_micros(ms * 1000)
+    }
+
+    fn scheduler() -> Scheduler<Job, 2> {
+        Scheduler::new(
+            [(Job::Fast, Duration::from_millis(100)), (Job::Slow, Duration::from_millis(1000))],
+            at(0),
+        )
+    }
+
+    // Poll every 10 ms up to `to_ms` and record when each task ran
+    fn run(scheduler: &mut Scheduler<Job, 2>, to_ms: u64) -> Vec<(u64, Job)> {
+        let mut ran = Vec::new();
+        for ms in (0..to_ms).step_by(10) {
+            while let Some(job) = scheduler.poll(at(ms)) {
+                ran.push((ms, job));
+            }
+        }
+        ran
+    }
+
+    #[test]
+    fn tasks_run_at_their_own_intervals() {
+        let ran = run(&mut scheduler(), 1100);
+        let fast: Vec<u64> = ran.iter().filter(|(_, job)| *job == Job::Fast).map(|(ms, _)| *ms).collect();
+        let slow: Vec<u64> = ran.iter().filter(|(_, job)| *job == Job::Slow).map(|(ms, _)| *ms).collect();
+        assert_eq!(fast, [0, 100, 200, 300, 400, 500, 600, 700, 800, 900, 1000]);
+        assert_eq!(slow, [0, 1000]);
+    }
+
+    #[test]
+    fn nothing_due_between_runs() {
+        let mut scheduler = scheduler();
+        assert_eq!(scheduler.poll(at(0)), Some(Job::Fast));
+        assert_eq!(scheduler.poll(at(0)), Some(Job::Slow));
+        assert_eq!(scheduler.poll(at(50)), None);
+        assert_eq!(scheduler.time_until_next(at(50)), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn most_overdue_task_runs_first() {
+        let mut scheduler = scheduler();
+        scheduler.run_after(Job::Fast, Duration::from_millis(30), at(0));
+        scheduler.run_after(Job::Slow, Duration::from_millis(20), at(0));
+        assert_eq!(scheduler.poll(at(40)), Some(Job::Slow));
+        assert_eq!(scheduler.poll(at(40)), Some(Job::Fast));
+    }
+
+    #[test]
+    fn late_task_does_not_burst() {
+        let mut scheduler = scheduler();
+        scheduler.poll(at(0));
+        scheduler.poll(at(0));
+        // the loop was stuck for 550 ms: the fast task runs once, then
+        // carries on 100 ms later
+        assert_eq!(scheduler.poll(at(550)), Some(Job::Fast));
+        assert_eq!(scheduler.poll(at(550)), None);
+        assert_eq!(scheduler.poll(at(650)), Some(Job::Fast));
+    }
+
+    #[test]
+    fn run_after_and_run_now_rebook_a_task() {
+        let mut scheduler = scheduler();
+        scheduler.run_after(Job::Slow, Duration::from_millis(500), at(0));
+        assert_eq!(scheduler.poll(at(0)), Some(Job::Fast));
+        assert_eq!(scheduler.poll(at(0)), None);
+        assert_eq!(scheduler.poll(at(499)), Some(Job::Fast));
+        assert_eq!(scheduler.poll(at(500)), Some(Job::Slow));
+
+        scheduler.run_now(Job::Slow, at(510));
+        assert_eq!(scheduler.poll(at(510)), Some(Job::Slow));
+    }
+
+    #[test]
+    fn set_interval_applies_from_next_run() {
+        let mut scheduler = scheduler();
+        scheduler.poll(at(0));
+        scheduler.poll(at(0));
+        scheduler.set_interval(Job::Fast, Duration::from_millis(300));
+        // already booked for 100 ms, then every 300 ms
+        assert_eq!(scheduler.poll(at(100)), Some(Job::Fast));
+        assert_eq!(scheduler.poll(at(200)), None);
+        assert_eq!(scheduler.poll(at(399)), None);
+        assert_eq!(scheduler.poll(at(400)), Some(Job::Fast));
+    }
+}
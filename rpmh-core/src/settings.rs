@@ -0,0 +1,399 @@
+use core::fmt::{self, Write};
+
+use crate::display::TemperatureUnit;
+use crate::export::Format;
+use crate::i2c_scan::SCAN_RANGE;
+use crate::leds::LedThresholds;
+use crate::time::Duration;
+
+// Time between sensor readings unless changed
+pub const DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+// Limits of the sample interval. The DHT20 needs a couple of seconds between
+// measurements to stay accurate (it heats itself up otherwise).
+pub const MIN_SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+pub const MAX_SAMPLE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Time between entries in the flash log unless changed. The log holds
+// about 120k entries, so this keeps over a year of history.
+pub const DEFAULT_LOG_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+// Decimals shown for readings. More than two don't fit on the LCD's reading
+// line (the sensor only resolves about 0.01 anyway).
+pub const DEFAULT_ROUNDING: u32 = 1;
+pub const MAX_ROUNDING: u32 = 2;
+
+// The settings that can be changed while the firmware runs (from the serial
+// console), kept in flash across resets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    pub sample_interval: Duration,
+    // Humidity bands of the LED bar
+    pub thresholds: LedThresholds,
+    // Unit temperatures are shown and streamed in
+    pub unit: TemperatureUnit,
+    // Whether the LCD backlight is on while a page is shown
+    pub backlight: bool,
+    // Battery saving: once the reading and metric pages have been shown,
+    // turn the LCD and the LED bar off until the next sample. Only has an
+    // effect when the sample interval is longer than the pages take (10 s).
+    // On by default in builds with the dark-idle feature.
+    pub dark_idle: bool,
+    // How readings are streamed over the serial port
+    pub format: Format,
+    // Decimals readings are rounded to on the LCD and in the text format
+    pub rounding: u32,
+    // Fixes the LCD's I2C address instead of scanning for it (read at boot)
+    pub lcd_address: Option<u8>,
+    // Time between entries in the flash log (independent of the sample
+    // interval), or None to stop logging
+    pub log_interval: Option<Duration>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            sample_interval: DEFAULT_SAMPLE_INTERVAL,
+            thresholds: LedThresholds::default(),
+            unit: TemperatureUnit::Celsius,
+            backlight: true,
+            dark_idle: cfg!(feature = "dark-idle"),
+            format: Format::Text,
+            rounding: DEFAULT_ROUNDING,
+            lcd_address: None,
+            log_interval: Some(DEFAULT_LOG_INTERVAL),
+        }
+    }
+}
+
+// Names one of the settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    Interval,
+    Thresholds,
+    Units,
+    Backlight,
+    DarkIdle,
+    Format,
+    Rounding,
+    LcdAddress,
+    LogInterval,
+}
+
+impl Setting {
+    pub const ALL: [Setting; 9] = [
+        Setting::Interval,
+        Setting::Thresholds,
+        Setting::Units,
+        Setting::Backlight,
+        Setting::DarkIdle,
+        Setting::Format,
+        Setting::Rounding,
+        Setting::LcdAddress,
+        Setting::LogInterval,
+    ];
+
+    // Name used on the console
+    pub fn name(&self) -> &'static str {
+        match self {
+            Setting::Interval => "interval",
+            Setting::Thresholds => "thresholds",
+            Setting::Units => "units",
+            Setting::Backlight => "backlight",
+            Setting::DarkIdle => "dark_idle",
+            Setting::Format => "format",
+            Setting::Rounding => "rounding",
+            Setting::LcdAddress => "lcd_address",
+            Setting::LogInterval => "log_interval",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Setting> {
+        Setting::ALL.into_iter().find(|setting| setting.name() == name)
+    }
+}
+
+// A new value for one of the settings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    Interval(Duration),
+    Thresholds(LedThresholds),
+    Units(TemperatureUnit),
+    Backlight(bool),
+    DarkIdle(bool),
+    Format(Format),
+    Rounding(u32),
+    LcdAddress(Option<u8>),
+    LogInterval(Option<Duration>),
+}
+
+impl Settings {
+    pub fn apply(&mut self, change: Change) {
+        match change {
+            Change::Interval(interval) => self.sample_interval = interval,
+            Change::Thresholds(thresholds) => self.thresholds = thresholds,
+            Change::Units(unit) => self.unit = unit,
+            Change::Backlight(on) => self.backlight = on,
+            Change::DarkIdle(on) => self.dark_idle = on,
+            Change::Format(format) => self.format = format,
+            Change::Rounding(rounding) => self.rounding = rounding,
+            Change::LcdAddress(address) => self.lcd_address = address,
+            Change::LogInterval(interval) => self.log_interval = interval,
+        }
+    }
+
+    // Write one setting as "name value", e.g. "interval 10 s"
+    pub fn write_setting<W: Write>(&self, out: &mut W, setting: Setting) -> fmt::Result {
+        write!(out, "{} ", setting.name())?;
+        match setting {
+            Setting::Interval => write!(out, "{} s", self.sample_interval.as_secs()),
+            Setting::Thresholds => {
+                let mut buffer = ryu::Buffer::new();
+                for (i, limit) in self.thresholds.limits().iter().enumerate() {
+                    let separator = if i == 0 { "" } else { " " };
+                    write!(out, "{}{}", separator, buffer.format(*limit))?;
+                }
+                Ok(())
+            }
+            Setting::Units => write!(out, "{}", self.unit.symbol()),
+            Setting::Backlight => out.write_str(if self.backlight { "on" } else { "off" }),
+            Setting::DarkIdle => out.write_str(if self.dark_idle { "on" } else { "off" }),
+            Setting::Format => out.write_str(self.format.name()),
+            Setting::Rounding => write!(out, "{}", self.rounding),
+            Setting::LcdAddress => match self.lcd_address {
+                Some(address) => write!(out, "0x{:02X}", address),
+                None => out.write_str("auto"),
+            },
+            Setting::LogInterval => match self.log_interval {
+                Some(interval) => write!(out, "{} s", interval.as_secs()),
+                None => out.write_str("off"),
+            },
+        }
+    }
+
+    // Pack the settings into the bytes kept in flash, little-endian:
+    //   0..4    sample interval in seconds
+    //   4..24   LED thresholds (five f32s)
+    //   24      unit (0 Celsius, 1 Fahrenheit)
+    //   25      backlight (0 off, 1 on)
+    //   26      format (0 text, 1 CSV, 2 JSON Lines)
+    //   27      rounding
+    //   28      LCD address (0 to scan for it)
+    //   29      dark when idle (0 off, 1 on)
+    //   30..34  log interval in seconds (0 for off)
+    // New settings go on the end, so the bytes of older firmware are a
+    // prefix of these (see SettingsStore).
+    pub fn to_bytes(&self) -> [u8; ENCODED_LEN] {
+        let mut bytes = [0; ENCODED_LEN];
+        let secs = self.sample_interval.as_secs() as u32;
+        bytes[0..4].copy_from_slice(&secs.to_le_bytes());
+        for (i, limit) in self.thresholds.limits().iter().enumerate() {
+            bytes[4 + 4 * i..8 + 4 * i].copy_from_slice(&limit.to_le_bytes());
+        }
+        bytes[24] = match self.unit {
+            TemperatureUnit::Celsius => 0,
+            TemperatureUnit::Fahrenheit => 1,
+        };
+        bytes[25] = self.backlight as u8;
+        bytes[26] = match self.format {
+            Format::Text => 0,
+            Format::Csv => 1,
+            Format::JsonLines => 2,
+        };
+        bytes[27] = self.rounding as u8;
+        bytes[28] = self.lcd_address.unwrap_or(0);
+        bytes[29] = self.dark_idle as u8;
+        let log_secs = self.log_interval.map_or(0, |interval| interval.as_secs() as u32);
+        bytes[30..34].copy_from_slice(&log_secs.to_le_bytes());
+        bytes
+    }
+
+    // Unpack settings written by to_bytes, or None if any value is out of
+    // range (so a damaged record isn't put to use)
+    pub fn from_bytes(bytes: &[u8; ENCODED_LEN]) -> Option<Settings> {
+        let field = |at: usize| [bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]];
+
+        let sample_interval = Duration::from_secs(u32::from_le_bytes(field(0)) as u64);
+        if !(MIN_SAMPLE_INTERVAL..=MAX_SAMPLE_INTERVAL).contains(&sample_interval) {
+            return None;
+        }
+        let mut limits = [0.0; 5];
+        for (i, limit) in limits.iter_mut().enumerate() {
+            *limit = f32::from_le_bytes(field(4 + 4 * i));
+        }
+        let thresholds = LedThresholds::new(limits).ok()?;
+        let unit = match bytes[24] {
+            0 => TemperatureUnit::Celsius,
+            1 => TemperatureUnit::Fahrenheit,
+            _ => return None,
+        };
+        let backlight = match bytes[25] {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        let format = match bytes[26] {
+            0 => Format::Text,
+            1 => Format::Csv,
+            2 => Format::JsonLines,
+            _ => return None,
+        };
+        let rounding = bytes[27] as u32;
+        if rounding > MAX_ROUNDING {
+            return None;
+        }
+        let lcd_address = match bytes[28] {
+            0 => None,
+            address if SCAN_RANGE.contains(&address) => Some(address),
+            _ => return None,
+        };
+        let dark_idle = match bytes[29] {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        let log_interval = match u32::from_le_bytes(field(30)) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs as u64)),
+        };
+        if log_interval.is_some_and(|interval| !(MIN_SAMPLE_INTERVAL..=MAX_SAMPLE_INTERVAL).contains(&interval)) {
+            return None;
+        }
+
+        Some(Settings {
+            sample_interval,
+            thresholds,
+            unit,
+            backlight,
+            dark_idle,
+            format,
+            rounding,
+            lcd_address,
+            log_interval,
+        })
+    }
+}
+
+// Size of the settings packed by to_bytes
+pub const ENCODED_LEN: usize = 34;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shown(settings: &Settings, setting: Setting) -> String {
+        let mut out = String::new();
+        settings.write_setting(&mut out, setting).unwrap();
+        out
+    }
+
+    #[test]
+    fn defaults_match_the_original_constants() {
+        let settings = Settings::default();
+        assert_eq!(settings.sample_interval, Duration::from_millis(10_000));
+        assert_eq!(settings.thresholds, LedThresholds::evenly_spaced());
+        assert_eq!(settings.unit, TemperatureUnit::Celsius);
+        assert!(settings.backlight);
+        assert_eq!(settings.dark_idle, cfg!(feature = "dark-idle"));
+        assert_eq!(settings.format, Format::Text);
+        assert_eq!(settings.rounding, 1);
+        assert_eq!(settings.lcd_address, None);
+        assert_eq!(settings.log_interval, Some(Duration::from_secs(300)));
+    }
+
+    #[test]
+    fn settings_are_written_by_name() {
+        let settings = Settings::default();
+        assert_eq!(shown(&settings, Setting::Interval), "interval 10 s");
+        assert_eq!(shown(&settings, Setting::Thresholds), "thresholds 0.0 20.0 40.0 60.0 80.0");
+        assert_eq!(shown(&settings, Setting::Units), "units C");
+        assert_eq!(shown(&settings, Setting::Backlight), "backlight on");
+        let dark_idle = if cfg!(feature = "dark-idle") { "dark_idle on" } else { "dark_idle off" };
+        assert_eq!(shown(&settings, Setting::DarkIdle), dark_idle);
+        assert_eq!(shown(&settings, Setting::Format), "format text");
+        assert_eq!(shown(&settings, Setting::Rounding), "rounding 1");
+        assert_eq!(shown(&settings, Setting::LcdAddress), "lcd_address auto");
+        assert_eq!(shown(&settings, Setting::LogInterval), "log_interval 300 s");
+    }
+
+    #[test]
+    fn changes_are_applied() {
+        let mut settings = Settings::default();
+        settings.apply(Change::Interval(Duration::from_secs(60)));
+        settings.apply(Change::Thresholds(LedThresholds::comfort()));
+        settings.apply(Change::Units(TemperatureUnit::Fahrenheit));
+        settings.apply(Change::Backlight(false));
+        settings.apply(Change::DarkIdle(true));
+        settings.apply(Change::Format(Format::Csv));
+        settings.apply(Change::Rounding(2));
+        settings.apply(Change::LcdAddress(Some(0x3F)));
+        settings.apply(Change::LogInterval(None));
+
+        assert_eq!(shown(&settings, Setting::Interval), "interval 60 s");
+        assert_eq!(settings.thresholds, LedThresholds::comfort());
+        assert_eq!(shown(&settings, Setting::Units), "units F");
+        assert_eq!(shown(&settings, Setting::Backlight), "backlight off");
+        assert_eq!(shown(&settings, Setting::DarkIdle), "dark_idle on");
+        assert_eq!(shown(&settings, Setting::Format), "format csv");
+        assert_eq!(shown(&settings, Setting::Rounding), "rounding 2");
+        assert_eq!(shown(&settings, Setting::LcdAddress), "lcd_address 0x3F");
+        assert_eq!(shown(&settings, Setting::LogInterval), "log_interval off");
+    }
+
+    fn changed() -> Settings {
+        Settings {
+            sample_interval: Duration::from_secs(600),
+            thresholds: LedThresholds::new([12.5, 30.0, 45.0, 60.0, 72.25]).unwrap(),
+            unit: TemperatureUnit::Fahrenheit,
+            backlight: false,
+            dark_idle: true,
+            format: Format::JsonLines,
+            rounding: 0,
+            lcd_address: Some(0x27),
+            log_interval: Some(Duration::from_secs(60)),
+        }
+    }
+
+    #[test]
+    fn settings_survive_packing() {
+        let logging_off = Settings {
+            log_interval: None,
+            ..changed()
+        };
+        for settings in [Settings::default(), changed(), logging_off] {
+            assert_eq!(Settings::from_bytes(&settings.to_bytes()), Some(settings));
+        }
+    }
+
+    #[test]
+    fn out_of_range_values_are_not_unpacked() {
+        let bytes = changed().to_bytes();
+        // interval of 1 s, a threshold over 100 %RH, unknown unit, backlight
+        // and format, too many decimals, reserved LCD address, unknown dark
+        // when idle, log interval of 1 s
+        let damage: [(usize, &[u8]); 9] = [
+            (0, &1u32.to_le_bytes()),
+            (4, &200f32.to_le_bytes()),
+            (24, &[2]),
+            (25, &[7]),
+            (26, &[3]),
+            (27, &[3]),
+            (28, &[0x7F]),
+            (29, &[2]),
+            (30, &1u32.to_le_bytes()),
+        ];
+        for (at, value) in damage {
+            let mut damaged = bytes;
+            damaged[at..at + value.len()].copy_from_slice(value);
+            assert_eq!(Settings::from_bytes(&damaged), None, "byte {}", at);
+        }
+    }
+
+    #[test]
+    fn settings_found_by_name() {
+        for setting in Setting::ALL {
+            assert_eq!(Setting::from_name(setting.name()), Some(setting));
+        }
+        assert_eq!(Setting::from_name("volume"), None);
+    }
+}
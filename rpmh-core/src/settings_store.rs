@@ -0,0 +1,357 @@
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::settings::{self, Settings};
+use crate::utils::crc32;
+
+// Keeps the settings in flash across resets.
+//
+// Flash wears out after around 100k erases of a sector, and a save
+// interrupted by a power cut must not lose the settings, so each save is
+// written to a fresh slot rather than over the last one. Two sectors are
+// used in turn: saves fill one slot after another, and once a sector is
+// full the other one is erased and filled next. The newest intact record
+// (highest sequence number, good CRC) is the one loaded, so a torn write
+// only loses the save that was in progress.
+
+// Erase unit of the flash chip
+pub const SECTOR_SIZE: u32 = 4096;
+// Each save takes one 256-byte flash page, the RP2040's programming unit
+pub const SLOT_SIZE: u32 = 256;
+const SLOTS_PER_SECTOR: u32 = SECTOR_SIZE / SLOT_SIZE;
+// Flash taken by the store
+pub const STORE_SIZE: u32 = 2 * SECTOR_SIZE;
+
+// Layout of a record at the start of its slot (the rest stays erased):
+//   0..4    "RPMH"
+//   4..6    record version, little-endian
+//   6..10   sequence number, one more than the previous save
+//   10..    the settings (see Settings::to_bytes), as long as they were in
+//           that record version
+//   then    CRC-32 of all the above
+const MAGIC: [u8; 4] = *b"RPMH";
+// Bumped whenever settings are added. Records of older versions are still
+// loaded, with the newer settings at their defaults; records of unknown
+// versions are ignored, so the defaults are used until the next save.
+pub const VERSION: u16 = 2;
+const VERSION_AT: usize = 4;
+const SEQUENCE_AT: usize = 6;
+const SETTINGS_AT: usize = 10;
+const RECORD_LEN: usize = SETTINGS_AT + settings::ENCODED_LEN + 4;
+
+// Length of the settings in each record version: version 2 added the log
+// interval
+fn settings_len(version: u16) -> Option<usize> {
+    match version {
+        1 => Some(30),
+        2 => Some(settings::ENCODED_LEN),
+        _ => None,
+    }
+}
+
+pub struct SettingsStore<F> {
+    flash: F,
+    // Offset of the first of the two sectors in the flash
+    base: u32,
+    // Slot and sequence number of the newest record, once looked for
+    latest: Option<Option<(u32, u32)>>,
+}
+
+impl<F: NorFlash> SettingsStore<F> {
+    // `base` must be sector aligned, with STORE_SIZE bytes of flash from it
+    // set aside for the store
+    pub fn new(flash: F, base: u32) -> Self {
+        Self {
+            flash,
+            base,
+            latest: None,
+        }
+    }
+
+    // The settings last saved, or None if there aren't any (nothing saved
+    // yet, or every record is damaged or from another firmware version)
+    pub fn load(&mut self) -> Result<Option<Settings>, F::Error> {
+        let mut newest: Option<(u32, u32, Settings)> = None;
+        for slot in 0..2 * SLOTS_PER_SECTOR {
+            let mut record = [0; RECORD_LEN];
+            self.flash.read(self.slot_offset(slot), &mut record)?;
+            let Some((sequence, settings)) = decode(&record) else {
+                continue;
+            };
+            if newest.is_none_or(|(_, newest_sequence, _)| sequence > newest_sequence) {
+                newest = Some((slot, sequence, settings));
+            }
+        }
+        self.latest = Some(newest.map(|(slot, sequence, _)| (slot, sequence)));
+        Ok(newest.map(|(_, _, settings)| settings))
+    }
+
+    pub fn save(&mut self, settings: &Settings) -> Result<(), F::Error> {
+        let latest = match self.latest {
+            Some(latest) => latest,
+            None => {
+                self.load()?;
+                self.latest.flatten()
+            }
+        };
+        let sequence = latest.map_or(0, |(_, sequence)| sequence.wrapping_add(1));
+        let slot = self.next_slot(latest)?;
+
+        let mut page = [0xFF; SLOT_SIZE as usize];
+        page[..RECORD_LEN].copy_from_slice(&encode(sequence, settings));
+        self.flash.write(self.slot_offset(slot), &page)?;
+        self.latest = Some(Some((slot, sequence)));
+        Ok(())
+    }
+
+    pub fn release(self) -> F {
+        self.flash
+    }
+
+    // The blank slot after the newest record, or the start of the other
+    // sector, erased, once that record's sector is used up
+    fn next_slot(&mut self, latest: Option<(u32, u32)>) -> Result<u32, F::Error> {
+        let sector = match latest {
+            Some((slot, _)) => {
+                let sector = slot / SLOTS_PER_SECTOR;
+                for next in slot + 1..(sector + 1) * SLOTS_PER_SECTOR {
+                    if self.is_blank(next)? {
+                        return Ok(next);
+                    }
+                }
+                1 - sector
+            }
+            // nothing (intact) saved yet
+            None => 0,
+        };
+        let from = self.base + sector * SECTOR_SIZE;
+        self.flash.erase(from, from + SECTOR_SIZE)?;
+        Ok(sector * SLOTS_PER_SECTOR)
+    }
+
+    fn is_blank(&mut self, slot: u32) -> Result<bool, F::Error> {
+        let mut record = [0; RECORD_LEN];
+        self.flash.read(self.slot_offset(slot), &mut record)?;
+        Ok(record.iter().all(|byte| *byte == 0xFF))
+    }
+
+    fn slot_offset(&self, slot: u32) -> u32 {
+        self.base + slot * SLOT_SIZE
+    }
+}
+
+fn encode(sequence: u32, settings: &Settings) -> [u8; RECORD_LEN] {
+    let crc_at = SETTINGS_AT + settings::ENCODED_LEN;
+    let mut record = [0; RECORD_LEN];
+    record[..VERSION_AT].copy_from_slice(&MAGIC);
+    record[VERSION_AT..SEQUENCE_AT].copy_from_slice(&VERSION.to_le_bytes());
+    record[SEQUENCE_AT..SETTINGS_AT].copy_from_slice(&sequence.to_le_bytes());
+    record[SETTINGS_AT..crc_at].copy_from_slice(&settings.to_bytes());
+    let crc = crc32(&record[..crc_at]);
+    record[crc_at..].copy_from_slice(&crc.to_le_bytes());
+    record
+}
+
+// The sequence number and settings of an intact record
+fn decode(record: &[u8; RECORD_LEN]) -> Option<(u32, Settings)> {
+    if record[..VERSION_AT] != MAGIC {
+        return None;
+    }
+    let version = u16::from_le_bytes([record[VERSION_AT], record[VERSION_AT + 1]]);
+    let len = settings_len(version)?;
+    let crc_at = SETTINGS_AT + len;
+    let crc = u32::from_le_bytes(record[crc_at..crc_at + 4].try_into().ok()?);
+    if crc != crc32(&record[..crc_at]) {
+        return None;
+    }
+    let sequence = u32::from_le_bytes(record[SEQUENCE_AT..SETTINGS_AT].try_into().ok()?);
+    // settings added since that version keep their defaults
+    let mut bytes = Settings::default().to_bytes();
+    bytes[..len].copy_from_slice(&record[SETTINGS_AT..crc_at]);
+    let settings = Settings::from_bytes(&bytes)?;
+    Some((sequence, settings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::Duration;
+    use crate::ram_flash::RamFlash;
+    use embedded_storage::nor_flash::{NorFlashError, NorFlashErrorKind};
+
+    // The store starts one sector in, so the sector before it must survive
+    const BASE: u32 = SECTOR_SIZE;
+
+    fn flash() -> RamFlash {
+        RamFlash::new(3)
+    }
+
+    fn with_interval(secs: u64) -> Settings {
+        Settings {
+            sample_interval: Duration::from_secs(secs),
+            ..Settings::default()
+        }
+    }
+
+    fn reopened(flash: RamFlash) -> (SettingsStore<RamFlash>, Option<Settings>) {
+        let mut store = SettingsStore::new(flash, BASE);
+        let settings = store.load().unwrap();
+        (store, settings)
+    }
+
+    #[test]
+    fn blank_flash_has_no_settings() {
+        let (_, settings) = reopened(flash());
+        assert_eq!(settings, None);
+    }
+
+    #[test]
+    fn saved_settings_load_after_a_reset() {
+        let mut store = SettingsStore::new(flash(), BASE);
+        store.save(&with_interval(60)).unwrap();
+        let (_, settings) = reopened(store.release());
+        assert_eq!(settings, Some(with_interval(60)));
+    }
+
+    #[test]
+    fn saves_take_turns_between_the_sectors() {
+        let (mut store, _) = reopened(flash());
+        for secs in 2..42 {
+            store.save(&with_interval(secs)).unwrap();
+        }
+        let flash = store.release();
+        // 40 saves: 16 in each sector, erasing it first, then 8 more in the first
+        assert_eq!(flash.erases, [BASE, BASE + SECTOR_SIZE, BASE]);
+        assert!(flash.bytes[..BASE as usize].iter().all(|byte| *byte == 0xFF));
+
+        let (_, settings) = reopened(flash);
+        assert_eq!(settings, Some(with_interval(41)));
+    }
+
+    #[test]
+    fn saving_carries_on_after_a_reset() {
+        let mut store = SettingsStore::new(flash(), BASE);
+        store.save(&with_interval(10)).unwrap();
+        store.save(&with_interval(20)).unwrap();
+        let (mut store, _) = reopened(store.release());
+        store.save(&with_interval(30)).unwrap();
+
+        let flash = store.release();
+        assert_eq!(flash.erases, [BASE]);
+        let (_, settings) = reopened(flash);
+        assert_eq!(settings, Some(with_interval(30)));
+    }
+
+    #[test]
+    fn torn_save_falls_back_to_the_previous_one() {
+        let mut store = SettingsStore::new(flash(), BASE);
+        store.save(&with_interval(10)).unwrap();
+        store.save(&with_interval(20)).unwrap();
+        let mut flash = store.release();
+        // power cut halfway through programming the second record
+        let second = (BASE + SLOT_SIZE) as usize;
+        flash.bytes[second + RECORD_LEN / 2..second + RECORD_LEN].fill(0xFF);
+
+        let (mut store, settings) = reopened(flash);
+        assert_eq!(settings, Some(with_interval(10)));
+        // the damaged slot is skipped on the next save
+        store.save(&with_interval(30)).unwrap();
+        let (_, settings) = reopened(store.release());
+        assert_eq!(settings, Some(with_interval(30)));
+    }
+
+    #[test]
+    fn damaged_flash_falls_back_to_defaults() {
+        let mut flash = flash();
+        for (i, byte) in flash.bytes.iter_mut().enumerate() {
+            *byte = (i * 7) as u8;
+        }
+        let (mut store, settings) = reopened(flash);
+        assert_eq!(settings, None);
+
+        store.save(&with_interval(90)).unwrap();
+        let (_, settings) = reopened(store.release());
+        assert_eq!(settings, Some(with_interval(90)));
+    }
+
+    #[test]
+    fn intact_records_of_nonsense_settings_are_ignored() {
+        // as if from a firmware with a bug, so the CRC doesn't catch it
+        let mut seed = 1u32;
+        for _ in 0..200 {
+            let mut bytes = [0; settings::ENCODED_LEN];
+            for byte in bytes.iter_mut() {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                *byte = (seed >> 16) as u8;
+            }
+            let mut record = Vec::new();
+            record.extend_from_slice(&MAGIC);
+            record.extend_from_slice(&VERSION.to_le_bytes());
+            record.extend_from_slice(&0u32.to_le_bytes());
+            record.extend_from_slice(&bytes);
+            record.extend_from_slice(&crc32(&record).to_le_bytes());
+            let mut flash = flash();
+            flash.bytes[BASE as usize..BASE as usize + record.len()].copy_from_slice(&record);
+
+            let (_, settings) = reopened(flash);
+            assert_eq!(settings, Settings::from_bytes(&bytes));
+        }
+    }
+
+    #[test]
+    fn records_of_another_version_are_ignored() {
+        let mut store = SettingsStore::new(flash(), BASE);
+        store.save(&with_interval(60)).unwrap();
+        let mut flash = store.release();
+        let at = (BASE as usize) + VERSION_AT;
+        flash.bytes[at..at + 2].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        // with a CRC to match, as that firmware would have written
+        let crc_at = BASE as usize + SETTINGS_AT + settings::ENCODED_LEN;
+        let crc = crc32(&flash.bytes[BASE as usize..crc_at]);
+        flash.bytes[crc_at..crc_at + 4].copy_from_slice(&crc.to_le_bytes());
+
+        let (_, settings) = reopened(flash);
+        assert_eq!(settings, None);
+    }
+
+    #[test]
+    fn version_1_records_load_with_logging_at_its_default() {
+        let saved = Settings {
+            log_interval: None,
+            ..with_interval(60)
+        };
+        // as written by the firmware before the log interval was added
+        let mut record = Vec::new();
+        record.extend_from_slice(b"RPMH");
+        record.extend_from_slice(&1u16.to_le_bytes());
+        record.extend_from_slice(&7u32.to_le_bytes());
+        record.extend_from_slice(&saved.to_bytes()[..30]);
+        record.extend_from_slice(&crc32(&record).to_le_bytes());
+        let mut flash = flash();
+        flash.bytes[BASE as usize..BASE as usize + record.len()].copy_from_slice(&record);
+
+        let (mut store, settings) = reopened(flash);
+        let expected = Settings {
+            log_interval: Settings::default().log_interval,
+            ..saved
+        };
+        assert_eq!(settings, Some(expected));
+
+        // saved again in the current version, after the old record
+        store.save(&expected).unwrap();
+        let flash = store.release();
+        let second = (BASE + SLOT_SIZE) as usize;
+        assert_eq!(flash.bytes[second + VERSION_AT..second + SEQUENCE_AT], VERSION.to_le_bytes());
+        let (_, settings) = reopened(flash);
+        assert_eq!(settings, Some(expected));
+    }
+
+    #[test]
+    fn flash_errors_are_passed_on() {
+        // a store placed past the end of the flash
+        let mut store = SettingsStore::new(RamFlash::new(1), SECTOR_SIZE);
+        let error = store.load().unwrap_err();
+        assert_eq!(error.kind(), NorFlashErrorKind::OutOfBounds);
+        let error = store.save(&Settings::default()).unwrap_err();
+        assert_eq!(error.kind(), NorFlashErrorKind::OutOfBounds);
+    }
+}
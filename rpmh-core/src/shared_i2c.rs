@@ -0,0 +1,142 @@
+use core::cell::RefCell;
+
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+
+// One I2C bus shared by several drivers, each of which wants to own its bus.
+// Every driver gets a BusDevice handle that borrows the bus just for the
+// length of one transaction, the same idea as embedded-hal-bus's RefCell
+// devices. A RefCell is enough because the bus is only used from the main
+// loop; it would need a critical-section Mutex if an interrupt handler ever
+// talked on it.
+pub struct SharedBus<I> {
+    bus: RefCell<I>,
+}
+
+impl<I> SharedBus<I> {
+    pub fn new(bus: I) -> Self {
+        SharedBus {
+            bus: RefCell::new(bus),
+        }
+    }
+
+    // A handle for one driver; any number can be handed out
+    pub fn device(&self) -> BusDevice<'_, I> {
+        BusDevice { bus: &self.bus }
+    }
+
+    // Use the bus directly, e.g. to configure or scan it
+    pub fn lock<R>(&self, f: impl FnOnce(&mut I) -> R) -> R {
+        f(&mut self.bus.borrow_mut())
+    }
+
+    pub fn into_inner(self) -> I {
+        self.bus.into_inner()
+    }
+}
+
+// A driver's handle to a SharedBus. It implements the blocking I2C traits,
+// so drivers can't tell it apart from owning the bus.
+#[derive(Clone, Copy)]
+pub struct BusDevice<'a, I> {
+    bus: &'a RefCell<I>,
+}
+
+impl<I> BusDevice<'_, I> {
+    // Use the bus directly, e.g. to configure it
+    pub fn lock<R>(&self, f: impl FnOnce(&mut I) -> R) -> R {
+        f(&mut self.bus.borrow_mut())
+    }
+}
+
+impl<I: Write> Write for BusDevice<'_, I> {
+    type Error = I::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.bus.borrow_mut().write(address, bytes)
+    }
+}
+
+impl<I: Read> Read for BusDevice<'_, I> {
+    type Error = I::Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.bus.borrow_mut().read(address, buffer)
+    }
+}
+
+impl<I: WriteRead> WriteRead for BusDevice<'_, I> {
+    type Error = I::Error;
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.bus.borrow_mut().write_read(address, bytes, buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bus double logging every transaction as (address, kind)
+    #[derive(Default)]
+    struct MockBus {
+        log: Vec<(u8, &'static str)>,
+    }
+
+    impl Write for MockBus {
+        type Error = ();
+
+        fn write(&mut self, address: u8, _bytes: &[u8]) -> Result<(), ()> {
+            self.log.push((address, "write"));
+            Ok(())
+        }
+    }
+
+    impl Read for MockBus {
+        type Error = ();
+
+        fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), ()> {
+            self.log.push((address, "read"));
+            buffer.fill(address);
+            Ok(())
+        }
+    }
+
+    impl WriteRead for MockBus {
+        type Error = ();
+
+        fn write_read(&mut self, address: u8, _bytes: &[u8], buffer: &mut [u8]) -> Result<(), ()> {
+            self.log.push((address, "write_read"));
+            buffer.fill(address);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn devices_take_turns_on_one_bus() {
+        let shared = SharedBus::new(MockBus::default());
+        let mut lcd = shared.device();
+        let mut sensor = shared.device();
+
+        lcd.write(0x27, &[0x08]).unwrap();
+        let mut status = [0];
+        sensor.read(0x38, &mut status).unwrap();
+        lcd.write(0x27, &[0x0C]).unwrap();
+        let mut frame = [0; 2];
+        sensor.write_read(0x38, &[0x71], &mut frame).unwrap();
+
+        assert_eq!(status, [0x38]);
+        assert_eq!(frame, [0x38, 0x38]);
+        assert_eq!(
+            shared.into_inner().log,
+            [(0x27, "write"), (0x38, "read"), (0x27, "write"), (0x38, "write_read")]
+        );
+    }
+
+    #[test]
+    fn lock_reaches_the_bus() {
+        let shared = SharedBus::new(MockBus::default());
+        let device = shared.device();
+        device.lock(|bus| bus.write(0x20, &[0])).unwrap();
+        assert_eq!(shared.lock(|bus| bus.log.len()), 1);
+    }
+}
@@ -0,0 +1,449 @@
+use core::fmt::{self, Write};
+use core::str::SplitWhitespace;
+
+use heapless::String;
+
+use crate::blink::{Blinker, FaultCode};
+use crate::display::TemperatureUnit;
+use crate::export::Format;
+use crate::leds::LedThresholds;
+use crate::i2c_scan::SCAN_RANGE;
+use crate::log_ring::LogRing;
+use crate::settings::{Change, Setting, MAX_ROUNDING, MAX_SAMPLE_INTERVAL, MIN_SAMPLE_INTERVAL};
+use crate::time::Duration;
+
+// A line-oriented command shell for the serial console. It only turns text
+// into commands and back; reading the bytes and carrying the commands out is
+// up to the firmware, so none of this depends on the USB transport.
+
+// Longest command line accepted (the longest useful one, setting five
+// thresholds, is well under this)
+pub const MAX_LINE: usize = 64;
+
+pub const PROMPT: &str = "> ";
+
+pub const HELP: &str = "\
+commands:\r
+  help                    this list\r
+  get [setting]           show one or all settings\r
+  set interval <seconds>  time between readings (2-86400)\r
+  set thresholds <5 x %RH | default | comfort | greenhouse | archive>\r
+  set units <c | f>       temperature unit\r
+  set backlight <on | off>\r
+  set dark_idle <on | off>  LCD and LEDs off between readings\r
+  set format <text | csv | json>  how readings are streamed\r
+  set rounding <0-2>      decimals shown\r
+  set lcd_address <auto | address>  used from the next reboot\r
+  set log_interval <seconds | off>  time between logged readings\r
+  read                    take a reading now\r
+  dump                    print the log in flash as CSV\r
+  status                  uptime, firmware version, faults and log\r
+  reboot                  restart the device\r
+";
+
+// A command typed at the console
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Help,
+    // One setting, or all of them
+    Get(Option<Setting>),
+    Set(Change),
+    Read,
+    // Print the readings logged in flash
+    Dump,
+    Status,
+    Reboot,
+}
+
+// Why a line couldn't be turned into a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellError {
+    LineTooLong,
+    UnknownCommand,
+    MissingSetting,
+    UnknownSetting,
+    MissingValue,
+    InvalidValue,
+    TooManyArguments,
+}
+
+impl ShellError {
+    pub fn description(&self) -> &'static str {
+        match self {
+            ShellError::LineTooLong => "line too long",
+            ShellError::UnknownCommand => "unknown command, try help",
+            ShellError::MissingSetting => "which setting?",
+            ShellError::UnknownSetting => "unknown setting, try get",
+            ShellError::MissingValue => "missing value",
+            ShellError::InvalidValue => "invalid value",
+            ShellError::TooManyArguments => "too many arguments",
+        }
+    }
+}
+
+// Parse one line, e.g. "set interval 30". Blank lines give None.
+pub fn parse(line: &str) -> Result<Option<Command>, ShellError> {
+    let mut words = line.split_whitespace();
+    let Some(name) = words.next() else {
+        return Ok(None);
+    };
+
+    let command = match name {
+        "help" | "?" => Command::Help,
+        "get" => Command::Get(words.next().map(parse_setting).transpose()?),
+        "set" => {
+            let setting = parse_setting(words.next().ok_or(ShellError::MissingSetting)?)?;
+            Command::Set(parse_change(setting, &mut words)?)
+        }
+        "read" => Command::Read,
+        "dump" => Command::Dump,
+        "status" => Command::Status,
+        "reboot" => Command::Reboot,
+        _ => return Err(ShellError::UnknownCommand),
+    };
+
+    if words.next().is_some() {
+        return Err(ShellError::TooManyArguments);
+    }
+    Ok(Some(command))
+}
+
+fn parse_setting(name: &str) -> Result<Setting, ShellError> {
+    Setting::from_name(name).ok_or(ShellError::UnknownSetting)
+}
+
+fn parse_change(setting: Setting, words: &mut SplitWhitespace) -> Result<Change, ShellError> {
+    let value = words.next().ok_or(ShellError::MissingValue)?;
+    match setting {
+        Setting::Interval => parse_interval(value).map(Change::Interval),
+        Setting::LogInterval => match value {
+            "off" => Ok(Change::LogInterval(None)),
+            _ => parse_interval(value).map(|interval| Change::LogInterval(Some(interval))),
+        },
+        Setting::Thresholds => {
+            let thresholds = match value {
+                "default" => LedThresholds::default(),
+                "comfort" => LedThresholds::comfort(),
+                "greenhouse" => LedThresholds::greenhouse(),
+                "archive" => LedThresholds::archive_storage(),
+                _ => {
+                    let mut limits = [0.0; 5];
+                    limits[0] = parse_limit(value)?;
+                    for limit in &mut limits[1..] {
+                        *limit = parse_limit(words.next().ok_or(ShellError::MissingValue)?)?;
+                    }
+                    LedThresholds::new(limits).map_err(|_| ShellError::InvalidValue)?
+                }
+            };
+            Ok(Change::Thresholds(thresholds))
+        }
+        Setting::Units => match value {
+            "c" | "C" => Ok(Change::Units(TemperatureUnit::Celsius)),
+            "f" | "F" => Ok(Change::Units(TemperatureUnit::Fahrenheit)),
+            _ => Err(ShellError::InvalidValue),
+        },
+        Setting::Backlight => parse_on_off(value).map(Change::Backlight),
+        Setting::DarkIdle => parse_on_off(value).map(Change::DarkIdle),
+        Setting::Format => Format::from_name(value)
+            .map(Change::Format)
+            .ok_or(ShellError::InvalidValue),
+        Setting::Rounding => match value.parse() {
+            Ok(rounding) if rounding <= MAX_ROUNDING => Ok(Change::Rounding(rounding)),
+            _ => Err(ShellError::InvalidValue),
+        },
+        Setting::LcdAddress => {
+            if value == "auto" {
+                return Ok(Change::LcdAddress(None));
+            }
+            // hex with a 0x prefix, as the backpack datasheets give it, or decimal
+            let address = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+                Some(hex) => u8::from_str_radix(hex, 16),
+                None => value.parse(),
+            };
+            match address {
+                Ok(address) if SCAN_RANGE.contains(&address) => Ok(Change::LcdAddress(Some(address))),
+                _ => Err(ShellError::InvalidValue),
+            }
+        }
+    }
+}
+
+fn parse_on_off(value: &str) -> Result<bool, ShellError> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(ShellError::InvalidValue),
+    }
+}
+
+// Whole seconds, within the range the sampling interval allows
+fn parse_interval(value: &str) -> Result<Duration, ShellError> {
+    let secs: u64 = value.parse().map_err(|_| ShellError::InvalidValue)?;
+    let interval = Duration::from_secs(secs);
+    if !(MIN_SAMPLE_INTERVAL..=MAX_SAMPLE_INTERVAL).contains(&interval) {
+        return Err(ShellError::InvalidValue);
+    }
+    Ok(interval)
+}
+
+fn parse_limit(value: &str) -> Result<f32, ShellError> {
+    value.parse().map_err(|_| ShellError::InvalidValue)
+}
+
+// Collects the bytes typed at the console into lines, echoing them back (a
+// serial terminal doesn't show what is typed by itself) and handling
+// backspace. Anything other than printable ASCII is ignored.
+#[derive(Default)]
+pub struct LineEditor {
+    line: String<MAX_LINE>,
+    // characters were dropped since the start of the line
+    overflowed: bool,
+    // the last byte ended a line with CR, so a following LF is part of it
+    after_cr: bool,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Take one received byte. Returns the line once it is ended by CR, LF
+    // or CRLF.
+    pub fn push<W: Write>(
+        &mut self,
+        byte: u8,
+        echo: &mut W,
+    ) -> Option<Result<String<MAX_LINE>, ShellError>> {
+        let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+        match byte {
+            b'\n' if after_cr => None,
+            b'\r' | b'\n' => {
+                let _ = echo.write_str("\r\n");
+                let line = core::mem::take(&mut self.line);
+                if core::mem::take(&mut self.overflowed) {
+                    Some(Err(ShellError::LineTooLong))
+                } else {
+                    Some(Ok(line))
+                }
+            }
+            // backspace and delete both rub out the last character
+            0x08 | 0x7F => {
+                if self.line.pop().is_some() {
+                    let _ = echo.write_str("\x08 \x08");
+                }
+                None
+            }
+            b' '..=b'~' => {
+                if self.line.push(byte as char).is_err() {
+                    self.overflowed = true;
+                }
+                let _ = echo.write_char(byte as char);
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+// What the status command reports
+pub struct Status<'a, const N: usize> {
+    pub uptime: Duration,
+    pub version: &'a str,
+    pub board: &'a str,
+    pub reset_reason: &'a str,
+    pub blinker: &'a Blinker,
+    // Why the flash data log couldn't be opened, if it couldn't
+    pub data_log_fault: Option<&'a str>,
+    pub log: &'a LogRing<N>,
+}
+
+// Answer to the status command, e.g.
+//   uptime 93 s
+//   firmware 0.1.0 (Rev 1)
+//   reset reason: Power on
+//   faults: SensorNack
+//   data log: off (no room in flash)
+//   log:
+//   INFO  Board Rev 1
+//   ...
+pub fn write_status<W: Write, const N: usize>(out: &mut W, status: &Status<N>) -> fmt::Result {
+    write!(out, "uptime {} s\r\n", status.uptime.as_secs())?;
+    write!(out, "firmware {} ({})\r\n", status.version, status.board)?;
+    write!(out, "reset reason: {}\r\n", status.reset_reason)?;
+    out.write_str("faults:")?;
+    if !status.blinker.any_raised() {
+        out.write_str(" none")?;
+    }
+    for code in FaultCode::ALL.into_iter().filter(|code| status.blinker.is_raised(*code)) {
+        write!(out, " {:?}", code)?;
+    }
+    out.write_str("\r\n")?;
+    // only mentioned when it couldn't be opened, and nothing is being logged
+    if let Some(fault) = status.data_log_fault {
+        write!(out, "data log: off ({})\r\n", fault)?;
+    }
+    out.write_str("log:")?;
+    if status.log.is_empty() {
+        out.write_str(" empty")?;
+    }
+    out.write_str("\r\n")?;
+    status.log.write_to(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(line: &str) -> Command {
+        parse(line).unwrap().unwrap()
+    }
+
+    #[test]
+    fn simple_commands() {
+        assert_eq!(parsed("help"), Command::Help);
+        assert_eq!(parsed("read"), Command::Read);
+        assert_eq!(parsed("dump"), Command::Dump);
+        assert_eq!(parsed("  status  "), Command::Status);
+        assert_eq!(parsed("reboot"), Command::Reboot);
+        assert_eq!(parse("   "), Ok(None));
+    }
+
+    #[test]
+    fn get_one_or_all_settings() {
+        assert_eq!(parsed("get"), Command::Get(None));
+        assert_eq!(parsed("get units"), Command::Get(Some(Setting::Units)));
+        assert_eq!(parse("get colour"), Err(ShellError::UnknownSetting));
+    }
+
+    #[test]
+    fn set_each_setting() {
+        assert_eq!(parsed("set interval 60"), Command::Set(Change::Interval(Duration::from_secs(60))));
+        assert_eq!(parsed("set units f"), Command::Set(Change::Units(TemperatureUnit::Fahrenheit)));
+        assert_eq!(parsed("set backlight off"), Command::Set(Change::Backlight(false)));
+        assert_eq!(parsed("set dark_idle on"), Command::Set(Change::DarkIdle(true)));
+        assert_eq!(parsed("set format json"), Command::Set(Change::Format(Format::JsonLines)));
+        assert_eq!(parsed("set rounding 2"), Command::Set(Change::Rounding(2)));
+        assert_eq!(parsed("set lcd_address 0x3F"), Command::Set(Change::LcdAddress(Some(0x3F))));
+        assert_eq!(parsed("set lcd_address 39"), Command::Set(Change::LcdAddress(Some(0x27))));
+        assert_eq!(parsed("set lcd_address auto"), Command::Set(Change::LcdAddress(None)));
+        assert_eq!(
+            parsed("set log_interval 900"),
+            Command::Set(Change::LogInterval(Some(Duration::from_secs(900))))
+        );
+        assert_eq!(parsed("set log_interval off"), Command::Set(Change::LogInterval(None)));
+        assert_eq!(
+            parsed("set thresholds greenhouse"),
+            Command::Set(Change::Thresholds(LedThresholds::greenhouse()))
+        );
+        assert_eq!(
+            parsed("set thresholds 10 20 30.5 40 90"),
+            Command::Set(Change::Thresholds(LedThresholds::new([10.0, 20.0, 30.5, 40.0, 90.0]).unwrap()))
+        );
+    }
+
+    #[test]
+    fn bad_values_are_rejected() {
+        assert_eq!(parse("set interval 1"), Err(ShellError::InvalidValue));
+        assert_eq!(parse("set interval soon"), Err(ShellError::InvalidValue));
+        assert_eq!(parse("set units k"), Err(ShellError::InvalidValue));
+        assert_eq!(parse("set dark_idle maybe"), Err(ShellError::InvalidValue));
+        assert_eq!(parse("set format xml"), Err(ShellError::InvalidValue));
+        assert_eq!(parse("set rounding 3"), Err(ShellError::InvalidValue));
+        assert_eq!(parse("set log_interval 0"), Err(ShellError::InvalidValue));
+        assert_eq!(parse("set log_interval never"), Err(ShellError::InvalidValue));
+        assert_eq!(parse("set lcd_address 0x7F"), Err(ShellError::InvalidValue));
+        assert_eq!(parse("set lcd_address 0xZZ"), Err(ShellError::InvalidValue));
+        assert_eq!(parse("set thresholds 10 20 30"), Err(ShellError::MissingValue));
+        assert_eq!(parse("set thresholds 50 40 30 20 10"), Err(ShellError::InvalidValue));
+        assert_eq!(parse("set backlight"), Err(ShellError::MissingValue));
+        assert_eq!(parse("set"), Err(ShellError::MissingSetting));
+    }
+
+    #[test]
+    fn unknown_and_extra_words_are_rejected() {
+        assert_eq!(parse("dance"), Err(ShellError::UnknownCommand));
+        assert_eq!(parse("read now"), Err(ShellError::TooManyArguments));
+        assert_eq!(parse("set units c please"), Err(ShellError::TooManyArguments));
+    }
+
+    // Feed a string through the editor, returning the lines and the echo
+    type Lines = Vec<Result<std::string::String, ShellError>>;
+
+    fn typed(editor: &mut LineEditor, input: &[u8]) -> (Lines, std::string::String) {
+        let mut echo = std::string::String::new();
+        let lines = input
+            .iter()
+            .filter_map(|byte| editor.push(*byte, &mut echo))
+            .map(|line| line.map(|line| line.as_str().into()))
+            .collect();
+        (lines, echo)
+    }
+
+    #[test]
+    fn editor_collects_lines() {
+        let mut editor = LineEditor::new();
+        let (lines, echo) = typed(&mut editor, b"get\r\nread\n");
+        assert_eq!(lines, [Ok("get".into()), Ok("read".into())]);
+        assert_eq!(echo, "get\r\nread\r\n");
+    }
+
+    #[test]
+    fn editor_handles_backspace() {
+        let mut editor = LineEditor::new();
+        let (lines, echo) = typed(&mut editor, b"rea\x7f\x7fead\r");
+        assert_eq!(lines, [Ok("read".into())]);
+        assert_eq!(echo, "rea\x08 \x08\x08 \x08ead\r\n");
+        // nothing to rub out on an empty line
+        let (_, echo) = typed(&mut editor, b"\x08");
+        assert_eq!(echo, "");
+    }
+
+    #[test]
+    fn editor_reports_long_lines() {
+        let mut editor = LineEditor::new();
+        let long = [b'x'; MAX_LINE + 1];
+        let (lines, _) = typed(&mut editor, &long);
+        assert!(lines.is_empty());
+        let (lines, _) = typed(&mut editor, b"\rhelp\r");
+        assert_eq!(lines, [Err(ShellError::LineTooLong), Ok("help".into())]);
+    }
+
+    #[test]
+    fn status_lists_raised_faults() {
+        let mut blinker = Blinker::new();
+        let mut log = LogRing::<4>::new();
+        let mut out = std::string::String::new();
+        let status = Status {
+            uptime: Duration::from_secs(93),
+            version: "0.1.0",
+            board: "Rev 1",
+            reset_reason: "Power on",
+            blinker: &blinker,
+            data_log_fault: None,
+            log: &log,
+        };
+        write_status(&mut out, &status).unwrap();
+        assert_eq!(
+            out,
+            "uptime 93 s\r\nfirmware 0.1.0 (Rev 1)\r\nreset reason: Power on\r\nfaults: none\r\nlog: empty\r\n"
+        );
+
+        blinker.raise(FaultCode::LcdNack);
+        blinker.raise(FaultCode::SensorNack);
+        log.push(log::Level::Warn, &format_args!("no LCD found on I2C0, trying 0x27"));
+        let mut out = std::string::String::new();
+        let status = Status {
+            uptime: Duration::ZERO,
+            version: "0.1.0",
+            board: "Rev 1",
+            reset_reason: "Watchdog reset",
+            blinker: &blinker,
+            data_log_fault: Some("flash error"),
+            log: &log,
+        };
+        write_status(&mut out, &status).unwrap();
+        assert!(out.ends_with(
+            "faults: SensorNack LcdNack\r\ndata log: off (flash error)\r\nlog:\r\nWARN  no LCD found on I2C0, trying 0x27\r\n"
+        ));
+    }
+}
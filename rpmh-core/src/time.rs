@@ -0,0 +1,169 @@
+use core::ops::{Add, Sub};
+
+// Instant and Duration mirror the std::time types of the same names, but
+// count whole microseconds in a u64 so they work in no_std. On the Pico they
+// come from the RP2040's 64-bit microsecond TIMER, which would take over
+// 500,000 years to wrap, so Instants can be compared directly.
+
+// A point in time, measured in microseconds since the clock started
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant {
+    micros: u64,
+}
+
+impl Instant {
+    pub const fn from_micros(micros: u64) -> Self {
+        Instant { micros }
+    }
+
+    pub const fn as_micros(&self) -> u64 {
+        self.micros
+    }
+
+    // Time passed between an earlier instant and this one (zero if `earlier`
+    // is actually later)
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_micros(self.micros.saturating_sub(earlier.micros))
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        Instant::from_micros(self.micros.saturating_add(duration.as_micros()))
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+// A span of time with microsecond resolution
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Duration {
+    micros: u64,
+}
+
+impl Duration {
+    pub const ZERO: Duration = Duration::from_micros(0);
+
+    pub const fn from_micros(micros: u64) -> Self {
+        Duration { micros }
+    }
+
+    pub const fn from_millis(millis: u64) -> Self {
+        Duration::from_micros(millis.saturating_mul(1_000))
+    }
+
+    pub const fn from_secs(secs: u64) -> Self {
+        Duration::from_micros(secs.saturating_mul(1_000_000))
+    }
+
+    pub const fn as_micros(&self) -> u64 {
+        self.micros
+    }
+
+    pub const fn as_millis(&self) -> u64 {
+        self.micros / 1_000
+    }
+
+    pub const fn as_secs(&self) -> u64 {
+        self.micros / 1_000_000
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    fn add(self, other: Duration) -> Duration {
+        Duration::from_micros(self.micros.saturating_add(other.micros))
+    }
+}
+
+impl Sub for Duration {
+    type Output = Duration;
+
+    fn sub(self, other: Duration) -> Duration {
+        Duration::from_micros(self.micros.saturating_sub(other.micros))
+    }
+}
+
+// A monotonic clock. The firmware implements this on
+// shared_delay::SharedTimer so hardware-independent code (e.g. the DHT20
+// driver) can measure elapsed time.
+pub trait Clock {
+    fn now(&self) -> Instant;
+
+    // Time passed since an earlier reading of this clock
+    fn elapsed(&self, since: Instant) -> Duration {
+        self.now().duration_since(since)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    struct MockClock {
+        now: Cell<Instant>,
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            self.now.get()
+        }
+    }
+
+    #[test]
+    fn duration_unit_conversions() {
+        assert_eq!(Duration::from_millis(1).as_micros(), 1_000);
+        assert_eq!(Duration::from_secs(10).as_millis(), 10_000);
+        assert_eq!(Duration::from_micros(1_999).as_millis(), 1);
+        assert_eq!(Duration::from_micros(2_500_000).as_secs(), 2);
+    }
+
+    #[test]
+    fn longest_delays_do_not_overflow() {
+        // DelayMs<u32> and DelayUs<u32> must cover their whole argument range
+        assert_eq!(Duration::from_millis(u32::MAX as u64).as_micros(), 4_294_967_295_000);
+        assert_eq!(Duration::from_micros(u32::MAX as u64).as_micros(), u32::MAX as u64);
+        assert_eq!(Duration::from_secs(u64::MAX).as_micros(), u64::MAX);
+    }
+
+    #[test]
+    fn instant_arithmetic() {
+        let start = Instant::from_micros(1_000);
+        let later = start + Duration::from_millis(5);
+
+        assert_eq!(later.as_micros(), 6_000);
+        assert_eq!(later - start, Duration::from_millis(5));
+        assert!(later > start);
+    }
+
+    #[test]
+    fn duration_since_saturates_at_zero() {
+        let start = Instant::from_micros(1_000);
+        let earlier = Instant::from_micros(500);
+
+        assert_eq!(earlier.duration_since(start), Duration::ZERO);
+        assert_eq!(Duration::from_micros(5) - Duration::from_micros(10), Duration::ZERO);
+    }
+
+    #[test]
+    fn elapsed_spans_32_bit_counter_rollover() {
+        // the RP2040 TIMER's low word wraps every ~71.6 minutes; the 64-bit
+        // count must not
+        let start = Instant::from_micros(u32::MAX as u64 - 10);
+        let clock = MockClock {
+            now: Cell::new(Instant::from_micros(u32::MAX as u64 + 990)),
+        };
+
+        assert_eq!(clock.elapsed(start), Duration::from_micros(1_000));
+    }
+}
@@ -0,0 +1,89 @@
+/*
+*  utils.rs is intended to remove math calculations and other details from
+*  main.rs.  This is particularly important in no-std programming. For example,
+*  the pow() operation is normally implemented inline with standard-lib integer
+*  and float methods, but in no-std it takes a number of lines that can clutter
+*  up a block of code.
+*/
+
+// Round a float to 0 or more decimals (base 10 only). Halves round away
+// from zero, so -2.45 becomes -2.5 just as 2.45 becomes 2.5.
+pub fn round_to_decimal(value: f32, rounding: u32) -> f32 {
+  // Shift left by 'rounding' digits, round to a whole number, then shift
+  // back rightward
+  let scale = pow(10.0, rounding);
+  libm::roundf(value * scale) / scale
+}
+
+// Generalized exponent function raising any base to any exponent >= 0
+// To make it more generalized, this could be improved by:
+//    - allowing a negative exponent
+//    - error handling, such as testing to ensure there is no overflow or
+//      incaccuracy from wraparound behavior with too high an exponent
+fn pow(base: f32, exp: u32) -> f32 {
+  // if exp is 1, result will drop past the if..else to return
+  let mut result: f32 = base;
+
+  // base^0 = 1
+  if exp == 0 {
+    result = 1.0;
+  } else {
+    for _ in 1..exp {
+      result *= base;
+    }
+  }
+
+  result
+}
+
+// CRC-32 (the zlib/Ethernet one), used to check records kept in flash.
+// Computed a bit at a time, as flash records are only a few dozen bytes.
+pub fn crc32(data: &[u8]) -> u32 {
+  let mut crc: u32 = 0xFFFF_FFFF;
+  for &byte in data {
+    crc ^= byte as u32;
+    for _ in 0..8 {
+      if crc & 1 != 0 {
+        crc = (crc >> 1) ^ 0xEDB8_8320;
+      } else {
+        crc >>= 1;
+      }
+    }
+  }
+  !crc
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_to_decimal_rounds_half_up() {
+    assert_eq!(round_to_decimal(55.32, 1), 55.3);
+    assert_eq!(round_to_decimal(55.35, 1), 55.4);
+    assert_eq!(round_to_decimal(55.5, 0), 56.0);
+    assert_eq!(round_to_decimal(55.4, 0), 55.0);
+  }
+
+  #[test]
+  fn round_to_decimal_rounds_negative_halves_away_from_zero() {
+    assert_eq!(round_to_decimal(-2.45, 1), -2.5);
+    assert_eq!(round_to_decimal(2.45, 1), 2.5);
+    assert_eq!(round_to_decimal(-2.44, 1), -2.4);
+    assert_eq!(round_to_decimal(-0.5, 0), -1.0);
+    assert_eq!(round_to_decimal(-12.345, 2), -12.35);
+  }
+
+  #[test]
+  fn pow_handles_zero_and_positive_exponents() {
+    assert_eq!(pow(10.0, 0), 1.0);
+    assert_eq!(pow(10.0, 1), 10.0);
+    assert_eq!(pow(10.0, 3), 1000.0);
+  }
+
+  #[test]
+  fn crc32_matches_the_standard_check_value() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(crc32(&[]), 0);
+  }
+}
@@ -40,17 +40,21 @@ where
 */
 #[entry]
 fn main() -> ! {
-    let mut rpp_core = pico::CoreComponents::setup_board();
+    let rpp_core = pico::CoreComponents::setup_board();
 
     let mut delays = shared_delay::Delays::new(&rpp_core.shared_timer);
 
+    // The LCD driver keeps a borrow of its bus handle
+    let mut lcd_i2c = rpp_core.i2c.lcd();
+
     let mut components = board::BoardComponents::setup_board(
         &rpp_core.shared_timer, 
-        rpp_core.sensor_i2c, 
-        &mut rpp_core.i2clcd,
-        &mut delays.lcd_delay,
+        rpp_core.i2c.sensor(), 
+        &mut lcd_i2c,
+        &mut delays.board_delay,
         rpp_core.led_pin_led, 
-        rpp_core.led_array
+        rpp_core.led_array,
+        rpp_core.addresses,
     );
 
     loop {
@@ -0,0 +1,105 @@
+// Compile without standard library
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::digital::v2::OutputPin;
+use rp_pico::entry;
+use OSU_RPMH::display::LcdLine;
+use OSU_RPMH::{board, pico, shared_delay};
+use OSU_RPMH::i2c_scan::{self, AddressSet};
+
+use liquidcrystal_i2c_rs::{Backlight, Display, Lcd};
+
+/*
+    Scans both I2C buses and shows what answered on the LCD, one page per bus
+    followed by the addresses picked for the LCD and the DHT20, e.g.
+
+        I2C0 LCD: 1         I2C1 sensor: 1      LCD 0x27
+        0x27                0x38                DHT20 0x38
+
+    Built with the shared-i2c feature both pages show the one shared bus
+    (I2C1 LCD / I2C1 sensor).
+
+    The boot-time scan is used to find the LCD, so if it doesn't light up at
+    all, no backpack answered at 0x20-0x27 or 0x38-0x3F and the on board LED
+    stays lit instead.
+
+    To run the test program, use the command $cargo run --bin scan_test
+*/
+
+// Print one page of scan results
+fn print_page<I, D>(
+    the_lcd: &mut Lcd<I, D>,
+    bus: &str,
+    device: &str,
+    found: &AddressSet,
+) -> Result<(), <I as embedded_hal::blocking::i2c::Write>::Error>
+where
+    I: embedded_hal::blocking::i2c::Write,
+    D: embedded_hal::blocking::delay::DelayMs<u8>,
+{
+    let mut line = LcdLine::new();
+    let _ = write!(line, "{} {}: {}", bus, device, found.len());
+
+    the_lcd.clear()?;
+    the_lcd.print(line.as_str())?;
+
+    the_lcd.set_cursor_position(0, 1)?;
+    the_lcd.print(i2c_scan::address_line(found).as_str())?;
+
+    Ok(())
+}
+
+#[entry]
+fn main() -> ! {
+    let rpp_core = pico::CoreComponents::setup_board();
+    let bus_scan = rpp_core.bus_scan;
+    let addresses = rpp_core.addresses;
+
+    let mut delays = shared_delay::Delays::new(&rpp_core.shared_timer);
+
+    // The LCD driver keeps a borrow of its bus handle
+    let mut lcd_i2c = rpp_core.i2c.lcd();
+
+    let mut components = board::BoardComponents::setup_board(
+        &rpp_core.shared_timer,
+        rpp_core.i2c.sensor(),
+        &mut lcd_i2c,
+        &mut delays.board_delay,
+        rpp_core.led_pin_led,
+        rpp_core.led_array,
+        rpp_core.addresses,
+    );
+
+    if !bus_scan.lcd_bus.contains(addresses.lcd) {
+        components.led_pin_led.set_high().unwrap();
+    }
+
+    let _ = components.lcd.set_display(Display::On);
+    let _ = components.lcd.set_backlight(Backlight::On);
+
+    loop {
+        let _ = print_page(&mut components.lcd, pico::LCD_BUS_NAME, "LCD", &bus_scan.lcd_bus);
+        delays.generic_delay.delay_ms(3000);
+
+        let _ = print_page(&mut components.lcd, pico::SENSOR_BUS_NAME, "sensor", &bus_scan.sensor_bus);
+        delays.generic_delay.delay_ms(3000);
+
+        let mut lcd_line = LcdLine::new();
+        let _ = write!(lcd_line, "LCD 0x{:02X}", addresses.lcd);
+        let mut sensor_line = LcdLine::new();
+        let _ = write!(sensor_line, "DHT20 0x{:02X}", addresses.sensor);
+        if !bus_scan.sensor_bus.contains(addresses.sensor) {
+            let _ = sensor_line.write_str(" ?");
+        }
+
+        let _ = components.lcd.clear();
+        let _ = components.lcd.print(lcd_line.as_str());
+        let _ = components.lcd.set_cursor_position(0, 1);
+        let _ = components.lcd.print(sensor_line.as_str());
+        delays.generic_delay.delay_ms(3000);
+    }
+}
@@ -1,17 +1,18 @@
 use crate::shared_delay::{DelayTimer, SharedTimer};
 use crate::leds;
+use crate::i2c_recovery::CycleDelay;
+use crate::hd44780;
+use crate::pico::{DeviceAddresses, LcdI2c, SensorI2c};
+use crate::profile;
+use embedded_hal::blocking::i2c::Write;
+use rpmh_core::shared_i2c::BusDevice;
 
 use rp_pico::hal;
-use rp_pico::hal::pac;
-
-// i2c elements
-use rp_pico::hal::gpio::{FunctionI2C, Pin};
 
 // custom adapted dht20 driver import
-use dht20::Dht20;
+use crate::dht::Dht20;
 
 use liquidcrystal_i2c_rs::{Lcd};
-static LCD_ADDRESS: u8 = 0x27;
 
 // Abstract the components we'll be using on the board into their own struct
 // This is useful for passing around the components in a single "object"
@@ -19,14 +20,11 @@ static LCD_ADDRESS: u8 = 0x27;
 pub struct BoardComponents<'a> {
     // DHT-20 humidity sensor
     pub sensor: Dht20<
-        hal::I2C<
-            pac::I2C1,
-            (
-                Pin<hal::gpio::bank0::Gpio18, FunctionI2C, hal::gpio::PullUp>,
-                Pin<hal::gpio::bank0::Gpio19, FunctionI2C, hal::gpio::PullUp>,
-            ),
-        >,
-        DelayTimer<'a>, 
+        'a,
+        BusDevice<'a, SensorI2c>,
+        DelayTimer<'a>,
+        SharedTimer,
+        hal::i2c::Error,
     >,
 
     // LED Outputs
@@ -37,54 +35,35 @@ pub struct BoardComponents<'a> {
     //   the same type here, we ensure compatibility between our struct
     //   definition and the initialization code in setup_board()
     // On board LED
-    pub led_pin_led:
-        Pin<hal::gpio::bank0::Gpio25, hal::gpio::FunctionSioOutput, hal::gpio::PullDown>,
+    pub led_pin_led: profile::OnboardLed,
 
     // A struct containing all indicator LEDs and methods to control their behavior
     pub led_array: leds::LedArray,
     
     // 1602 LCD visual display
-    pub lcd: Lcd<'a, 
-        hal::I2C<
-            pac::I2C0,
-            (
-                Pin<hal::gpio::bank0::Gpio0, FunctionI2C, hal::gpio::PullUp>,
-                Pin<hal::gpio::bank0::Gpio1, FunctionI2C, hal::gpio::PullUp>,
-            ),
-        >,
-        DelayTimer<'a>,
-    >,
+    pub lcd: Lcd<'a, BusDevice<'a, LcdI2c>, DelayTimer<'a>>,
 }
 
 impl<'a> BoardComponents<'a> {
     // Set up all of our board components and return them in a single struct
     pub fn setup_board(shared_timer: &'a SharedTimer, 
-        sensor_i2c: hal::I2C<
-            pac::I2C1,
-            (
-                Pin<hal::gpio::bank0::Gpio18, FunctionI2C, hal::gpio::PullUp>,
-                Pin<hal::gpio::bank0::Gpio19, FunctionI2C, hal::gpio::PullUp>,
-            ),
-        >,
-        lcd_i2c: &'a mut hal::I2C<
-            pac::I2C0, 
-            (
-                Pin<hal::gpio::bank0::Gpio0, FunctionI2C, hal::gpio::PullUp>, 
-                Pin<hal::gpio::bank0::Gpio1, FunctionI2C, hal::gpio::PullUp>
-            ),
-        >,
-        lcd_delay: &'a mut DelayTimer<'a>,
-        led_pin_led: Pin<hal::gpio::bank0::Gpio25, hal::gpio::FunctionSioOutput, hal::gpio::PullDown>,
+        sensor_i2c: BusDevice<'a, SensorI2c>,
+        lcd_i2c: &'a mut BusDevice<'a, LcdI2c>,
+        board_delay: &'a mut DelayTimer<'a>,
+        led_pin_led: profile::OnboardLed,
         led_array: leds::LedArray,
+        addresses: DeviceAddresses,
     ) -> BoardComponents<'a> {
-        // Create sensor delay
-        let sensor_delay = DelayTimer::new(&shared_timer);
+        // Set up DHT20 sensor (the shared timer enforces its minimum read interval)
+        // The sensor gets a copy of the board delay, so it shares one delay with the LCD
+        let sensor = Dht20::new(sensor_i2c, addresses.sensor, *board_delay, shared_timer);
 
-        // Set up DHT20 sensor
-        let sensor = Dht20::new(sensor_i2c, 0x38, sensor_delay);
-
-        // Set up LCD 
-        let lcd = Lcd::new(lcd_i2c, LCD_ADDRESS, lcd_delay).unwrap();
+        // Set up LCD (and how to bring it back if its bus has to be
+        // recovered, whichever device wedged it; the sensor re-initializes
+        // itself on every read)
+        // The LCD address was picked by scanning its bus (0x27 or 0x3F etc.)
+        lcd_i2c.lock(|bus| bus.set_reinit(reinit_lcd, addresses.lcd));
+        let lcd = Lcd::new(lcd_i2c, addresses.lcd, board_delay).unwrap();
 
         // Return all components in the form of the struct (LCD will need to be added here as well)
         BoardComponents {
@@ -95,3 +74,8 @@ impl<'a> BoardComponents<'a> {
         }
     }
 }
+
+// Re-initialize the LCD controller after its bus has been recovered
+fn reinit_lcd<B: Write>(bus: &mut B, address: u8, delay: &mut CycleDelay) {
+    let _ = hd44780::reinit(bus, address, delay);
+}
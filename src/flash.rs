@@ -0,0 +1,95 @@
+use embedded_storage::nor_flash::{
+    check_erase, check_read, check_write, ErrorType, MultiwriteNorFlash, NorFlash, NorFlashErrorKind,
+    ReadNorFlash,
+};
+use rp2040_flash::flash;
+
+use rpmh_core::export::DeviceId;
+use rpmh_core::settings_store::{SECTOR_SIZE, STORE_SIZE};
+
+// Access to the Pico's QSPI flash chip, the one the firmware runs from.
+//
+// While the flash is being talked to directly it can't be read through the
+// XIP cache, so these run from RAM (inside rp2040-flash) with interrupts
+// off. Core 1 isn't used by this firmware, so nothing else can be executing
+// from flash meanwhile.
+
+// The Pico's 2 MB chip, as declared in memory.x
+pub const FLASH_SIZE: u32 = 2048 * 1024;
+
+// Where the flash appears in the address space (through the XIP cache)
+const XIP_BASE: u32 = 0x1000_0000;
+
+// The settings take the last two sectors; memory.x keeps the firmware out
+// of them
+pub const SETTINGS_OFFSET: u32 = FLASH_SIZE - STORE_SIZE;
+
+// The data log takes the whole sectors between the end of the firmware image
+// and the settings, returned as (start, end) offsets. The image ends with
+// the initial values of .data, which cortex-m-rt's linker script puts after
+// .text and .rodata (and exports the symbols for).
+pub fn log_region() -> (u32, u32) {
+    extern "C" {
+        static __sidata: u8;
+        static __sdata: u8;
+        static __edata: u8;
+    }
+    let image_end = unsafe {
+        let data_len = &__edata as *const u8 as u32 - &__sdata as *const u8 as u32;
+        &__sidata as *const u8 as u32 + data_len - XIP_BASE
+    };
+    (image_end.next_multiple_of(SECTOR_SIZE), SETTINGS_OFFSET)
+}
+
+// The flash chip's factory programmed 64-bit ID, unique to each Pico
+pub fn unique_id() -> DeviceId {
+    let mut id = [0; 8];
+    cortex_m::interrupt::free(|_| unsafe { flash::flash_unique_id(&mut id, true) });
+    DeviceId(id)
+}
+
+// The whole flash chip as embedded-storage NOR flash, offsets counting from
+// its start. Nothing stops this from erasing the firmware itself, so it is
+// only handed to stores set up clear of the image.
+pub struct PicoFlash;
+
+impl ErrorType for PicoFlash {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for PicoFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check_read(self, offset, bytes.len())?;
+        let from = (XIP_BASE + offset) as *const u8;
+        unsafe { core::ptr::copy_nonoverlapping(from, bytes.as_mut_ptr(), bytes.len()) };
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        FLASH_SIZE as usize
+    }
+}
+
+impl NorFlash for PicoFlash {
+    // The chip programs whole 256-byte pages and erases 4 KB sectors
+    const WRITE_SIZE: usize = 256;
+    const ERASE_SIZE: usize = SECTOR_SIZE as usize;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        check_erase(self, from, to)?;
+        cortex_m::interrupt::free(|_| unsafe { flash::flash_range_erase(from, to - from, true) });
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_write(self, offset, bytes.len())?;
+        cortex_m::interrupt::free(|_| unsafe { flash::flash_range_program(offset, bytes, true) });
+        Ok(())
+    }
+}
+
+// Programming only ever clears bits, so a page can be programmed again with
+// 0xFF over the parts already written (the data log relies on this)
+impl MultiwriteNorFlash for PicoFlash {}
@@ -0,0 +1,218 @@
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+use rp_pico::hal::gpio::PinState;
+use log::warn;
+use rp_pico::hal;
+use rp_pico::hal::fugit::HertzU32;
+use rp_pico::hal::gpio::{FunctionI2C, Pin, PullUp};
+use rp_pico::hal::pac;
+use crate::profile;
+use rpmh_core::i2c_recovery::{self, FailureCounter};
+use rpmh_core::i2c_scan::{self, AddressSet};
+
+// The two I2C buses on this board, on the pins given by its profile
+pub type SensorBus = hal::I2C<
+    pac::I2C1,
+    (
+        Pin<profile::SensorSda, FunctionI2C, PullUp>,
+        Pin<profile::SensorScl, FunctionI2C, PullUp>,
+    ),
+>;
+pub type LcdBus = hal::I2C<
+    pac::I2C0,
+    (
+        Pin<profile::LcdSda, FunctionI2C, PullUp>,
+        Pin<profile::LcdScl, FunctionI2C, PullUp>,
+    ),
+>;
+
+// Busy-wait delay counted in CPU cycles. Recovery happens deep inside a bus
+// transaction, where the shared timer isn't reachable, so this only needs
+// the system clock frequency.
+#[derive(Clone, Copy)]
+pub struct CycleDelay {
+    cycles_per_us: u32,
+}
+
+impl CycleDelay {
+    pub fn new(system_clock: HertzU32) -> Self {
+        Self {
+            cycles_per_us: system_clock.to_MHz(),
+        }
+    }
+}
+
+impl DelayUs<u16> for CycleDelay {
+    fn delay_us(&mut self, us: u16) {
+        cortex_m::asm::delay(us as u32 * self.cycles_per_us);
+    }
+}
+
+impl DelayMs<u8> for CycleDelay {
+    fn delay_ms(&mut self, ms: u8) {
+        for _ in 0..ms {
+            self.delay_us(1000);
+        }
+    }
+}
+
+// An I2C bus that can be taken apart and put back together to clear a stuck
+// bus (a device holding SDA low after a glitch)
+pub trait Recover: Sized {
+    // Free the peripheral, clock the device off SDA and send a STOP with the
+    // pins as plain GPIOs, then create the peripheral again
+    fn recover(self, frequency: HertzU32, system_clock: HertzU32) -> Self;
+}
+
+macro_rules! impl_recover {
+    ($bus:ty, $constructor:ident) => {
+        impl Recover for $bus {
+            fn recover(self, frequency: HertzU32, system_clock: HertzU32) -> Self {
+                // SAFETY: the I2C helpers only flip this block's own reset
+                // bit, and nothing else touches RESETS once setup_board is done
+                let mut peripherals = unsafe { pac::Peripherals::steal() };
+                let resets = &mut peripherals.RESETS;
+
+                let (block, (sda, scl)) = self.free(resets);
+                let mut delay = CycleDelay::new(system_clock);
+
+                let mut scl = scl.into_push_pull_output_in_state(PinState::High);
+                let sda = sda.into_pull_up_input();
+                i2c_recovery::clock_out(&mut scl, &sda, &mut delay);
+                let mut sda = sda.into_push_pull_output_in_state(PinState::High);
+                i2c_recovery::send_stop(&mut scl, &mut sda, &mut delay);
+
+                hal::I2C::$constructor(
+                    block,
+                    sda.reconfigure(),
+                    scl.reconfigure(),
+                    frequency,
+                    resets,
+                    system_clock,
+                )
+            }
+        }
+    };
+}
+
+impl_recover!(SensorBus, i2c1);
+impl_recover!(LcdBus, i2c0);
+
+// Brings a device back on the bare bus after a recovery: gets the bus, the
+// device's address and a delay
+type Reinit<B> = fn(&mut B, u8, &mut CycleDelay);
+
+// Wraps an I2C bus and recovers it automatically after a run of failed
+// transactions (DEFAULT_FAILURE_THRESHOLD in a row). The failing transaction
+// still returns its error; the ones after the recovery go to the fresh bus.
+// Drivers use it like the bus itself, as it implements the same traits.
+pub struct RecoveringI2c<B> {
+    // only None while the bus is being recovered
+    bus: Option<B>,
+    frequency: HertzU32,
+    system_clock: HertzU32,
+    failures: FailureCounter,
+    // brings the attached device (at the given address) back after a
+    // recovery, e.g. the LCD
+    reinit: Option<(Reinit<B>, u8)>,
+    recoveries: u32,
+}
+
+impl<B: Recover> RecoveringI2c<B> {
+    // Takes the bus along with the frequencies it was created with, which
+    // are needed to create it again
+    pub fn new(bus: B, frequency: HertzU32, system_clock: HertzU32) -> Self {
+        Self {
+            bus: Some(bus),
+            frequency,
+            system_clock,
+            failures: FailureCounter::default(),
+            reinit: None,
+            recoveries: 0,
+        }
+    }
+
+    // Function run on the bare bus after each recovery to re-initialize the
+    // device at `address`
+    pub fn set_reinit(&mut self, reinit: Reinit<B>, address: u8) {
+        self.reinit = Some((reinit, address));
+    }
+
+    pub fn set_failure_threshold(&mut self, threshold: u8) {
+        self.failures = FailureCounter::new(threshold);
+    }
+
+    // Number of times the bus has been recovered since boot
+    pub fn recoveries(&self) -> u32 {
+        self.recoveries
+    }
+
+    // Clear the bus and re-initialize the device now
+    pub fn recover(&mut self) {
+        if let Some(bus) = self.bus.take() {
+            let mut bus = bus.recover(self.frequency, self.system_clock);
+            if let Some((reinit, address)) = self.reinit {
+                reinit(&mut bus, address, &mut CycleDelay::new(self.system_clock));
+            }
+            self.bus = Some(bus);
+            self.recoveries += 1;
+            warn!("I2C bus recovered ({} so far)", self.recoveries);
+        }
+    }
+
+    // List the addresses that answer on the bus. The probes of empty
+    // addresses fail by design, so they don't count towards a recovery.
+    pub fn scan(&mut self) -> AddressSet
+    where
+        B: Read,
+    {
+        i2c_scan::scan(self.bus())
+    }
+
+    fn bus(&mut self) -> &mut B {
+        self.bus.as_mut().unwrap()
+    }
+
+    fn record<T, E>(&mut self, result: Result<T, E>) -> Result<T, E> {
+        if self.failures.record(result.is_ok()) {
+            self.recover();
+        }
+        result
+    }
+}
+
+impl<B, E> Write for RecoveringI2c<B>
+where
+    B: Recover + Write<Error = E>,
+{
+    type Error = E;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), E> {
+        let result = self.bus().write(address, bytes);
+        self.record(result)
+    }
+}
+
+impl<B, E> Read for RecoveringI2c<B>
+where
+    B: Recover + Read<Error = E>,
+{
+    type Error = E;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), E> {
+        let result = self.bus().read(address, buffer);
+        self.record(result)
+    }
+}
+
+impl<B, E> WriteRead for RecoveringI2c<B>
+where
+    B: Recover + WriteRead<Error = E>,
+{
+    type Error = E;
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), E> {
+        let result = self.bus().write_read(address, bytes, buffer);
+        self.record(result)
+    }
+}
@@ -1,82 +1,83 @@
-use embedded_hal::digital::v2::OutputPin;
-use rp_pico::hal::gpio::{
-    self,
-    bank0::{Gpio12, Gpio13, Gpio14, Gpio15, Gpio16},
-    DefaultTypeState, Pin,
+use rp_pico::hal::gpio::{self, DynPinId, Pin};
+use rp_pico::hal::pwm::{
+    Channel, FreeRunning, Pwm0, Pwm1, Pwm2, Pwm3, Pwm4, Pwm5, Pwm6, Pwm7, Slice, A, B,
 };
 
-// A struct for interacting with the LED Array
-pub struct LedArray {
-    led_pin_red: Pin<Gpio15, gpio::FunctionSioOutput, gpio::PullDown>,
-    led_pin_yellow: Pin<Gpio14, gpio::FunctionSioOutput, gpio::PullDown>,
-    led_pin_green: Pin<Gpio16, gpio::FunctionSioOutput, gpio::PullDown>,
-    led_pin_yellow2: Pin<Gpio13, gpio::FunctionSioOutput, gpio::PullDown>,
-    led_pin_red2: Pin<Gpio12, gpio::FunctionSioOutput, gpio::PullDown>,
-}
+// Any GPIO configured as a push-pull output. Converting the individual pins
+// to this type (with into_dyn_pin()) lets the LedArray hold them in one array,
+// so moving an LED to another GPIO only means changing the board profile
+// (see profile.rs).
+pub type LedPin = Pin<DynPinId, gpio::FunctionSioOutput, gpio::PullDown>;
 
-impl LedArray {
-    // Creates a new LedArray using the five pins passed as arguments
-    pub fn new(
-        gpio12: Pin<
-            Gpio12,
-            <Gpio12 as DefaultTypeState>::Function,
-            <Gpio12 as DefaultTypeState>::PullType,
-        >,
-        gpio13: Pin<
-            Gpio13,
-            <Gpio13 as DefaultTypeState>::Function,
-            <Gpio13 as DefaultTypeState>::PullType,
-        >,
-        gpio14: Pin<
-            Gpio14,
-            <Gpio14 as DefaultTypeState>::Function,
-            <Gpio14 as DefaultTypeState>::PullType,
-        >,
-        gpio15: Pin<
-            Gpio15,
-            <Gpio15 as DefaultTypeState>::Function,
-            <Gpio15 as DefaultTypeState>::PullType,
-        >,
-        gpio16: Pin<
-            Gpio16,
-            <Gpio16 as DefaultTypeState>::Function,
-            <Gpio16 as DefaultTypeState>::PullType,
-        >,
-    ) -> Self {
-        LedArray {
-            led_pin_red: gpio15.into_push_pull_output(),
-            led_pin_yellow: gpio14.into_push_pull_output(),
-            led_pin_green: gpio16.into_push_pull_output(),
-            led_pin_yellow2: gpio13.into_push_pull_output(),
-            led_pin_red2: gpio12.into_push_pull_output(),
+// Declare LedChannel with a variant per PWM channel, converting from the
+// hal's channel types
+macro_rules! led_channels {
+    ($($variant:ident: $slice:ident $channel:ident),*) => {
+        // The PWM channels that can drive an LED. Each channel is its own type
+        // in rp2040-hal, so they are wrapped in one enum to fit in the
+        // LedArray. Which channel a GPIO is on is fixed by the RP2040 (GPIO n
+        // is on slice n / 2 mod 8, channel A when n is even), e.g.
+        //   GPIO12 -> slice 6 A, GPIO13 -> slice 6 B, GPIO16 -> slice 0 A
+        pub enum LedChannel {
+            $($variant(Channel<Slice<$slice, FreeRunning>, $channel>),)*
         }
+
+        $(
+            impl From<Channel<Slice<$slice, FreeRunning>, $channel>> for LedChannel {
+                fn from(channel: Channel<Slice<$slice, FreeRunning>, $channel>) -> Self {
+                    LedChannel::$variant(channel)
+                }
+            }
+        )*
+
+        // Forward a PwmPin call to whichever channel is inside
+        macro_rules! with_channel {
+            ($self:expr, $bound:ident => $body:expr) => {
+                match $self {
+                    $(LedChannel::$variant($bound) => $body,)*
+                }
+            };
+        }
+    };
+}
+
+led_channels!(
+    Pwm0A: Pwm0 A, Pwm0B: Pwm0 B, Pwm1A: Pwm1 A, Pwm1B: Pwm1 B,
+    Pwm2A: Pwm2 A, Pwm2B: Pwm2 B, Pwm3A: Pwm3 A, Pwm3B: Pwm3 B,
+    Pwm4A: Pwm4 A, Pwm4B: Pwm4 B, Pwm5A: Pwm5 A, Pwm5B: Pwm5 B,
+    Pwm6A: Pwm6 A, Pwm6B: Pwm6 B, Pwm7A: Pwm7 A, Pwm7B: Pwm7 B
+);
+
+impl embedded_hal::PwmPin for LedChannel {
+    type Duty = u16;
+
+    fn disable(&mut self) {
+        with_channel!(self, channel => channel.disable())
     }
 
-    // Turn off all leds
-    pub fn clear(&mut self) {
-        self.led_pin_red.set_low().unwrap();
-        self.led_pin_yellow.set_low().unwrap();
-        self.led_pin_green.set_low().unwrap();
-        self.led_pin_yellow2.set_low().unwrap();
-        self.led_pin_red2.set_low().unwrap();
+    fn enable(&mut self) {
+        with_channel!(self, channel => channel.enable())
     }
 
-    // Update the LED array based on the humidity reading
-    pub fn update(&mut self, humidity: &f32) {
-        if *humidity > 0.0 {
-            self.led_pin_red.set_high().unwrap();
-        }
-        if *humidity > 20.0 {
-            self.led_pin_yellow.set_high().unwrap();
-        }
-        if *humidity > 40.0 {
-            self.led_pin_green.set_high().unwrap();
-        }
-        if *humidity > 60.0 {
-            self.led_pin_yellow2.set_high().unwrap();
-        }
-        if *humidity > 80.0 {
-            self.led_pin_red2.set_high().unwrap();
-        }
+    fn get_duty(&self) -> u16 {
+        with_channel!(self, channel => channel.get_duty())
+    }
+
+    fn get_max_duty(&self) -> u16 {
+        with_channel!(self, channel => channel.get_max_duty())
+    }
+
+    fn set_duty(&mut self, duty: u16) {
+        with_channel!(self, channel => channel.set_duty(duty))
     }
 }
+
+// The board's five LED bar graph, bottom (red) to top (red2). The generic
+// LedArray and its threshold logic live in rpmh_core::leds. With the
+// pwm-leds feature (on by default) the LEDs can be dimmed and faded;
+// without it they fall back to plain on/off GPIO outputs.
+#[cfg(feature = "pwm-leds")]
+pub type LedOutput = rpmh_core::leds::PwmLed<LedChannel>;
+#[cfg(not(feature = "pwm-leds"))]
+pub type LedOutput = LedPin;
+pub type LedArray = rpmh_core::leds::LedArray<LedOutput, 5>;
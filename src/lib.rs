@@ -3,9 +3,19 @@
 #![no_main]
 #![allow(non_snake_case)] // Allow our crate to have a non-snake-case name
 
+use panic_halt as _;
+
 pub mod board; 
-pub mod dht;
+pub mod flash;
+pub mod i2c_recovery;
 pub mod leds;
+pub mod logger;
 pub mod pico;
-pub mod utils;
+pub mod profile;
 pub mod shared_delay;
+pub mod sleep;
+pub mod usb_serial;
+
+// Hardware-independent modules live in the rpmh-core crate (so they can be
+// tested on the host) and are re-exported here under their original paths
+pub use rpmh_core::{blink, data_log, dht, display, export, hd44780, i2c_scan, log_ring, psychrometrics, report, scheduler, settings, settings_store, shared_i2c, shell, time, utils};
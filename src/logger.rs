@@ -0,0 +1,60 @@
+use core::cell::RefCell;
+
+use cortex_m::interrupt::Mutex;
+use log::{LevelFilter, Log, Metadata, Record};
+use rpmh_core::log_ring::LogRing;
+
+// Messages kept for the status command: the boot messages take about a
+// dozen lines, leaving room for the warnings raised while running
+pub const KEPT_MESSAGES: usize = 16;
+
+// Messages above this level are thrown away (the housekeeping task's debug
+// line would otherwise push everything else out within seconds)
+const MAX_LEVEL: LevelFilter = LevelFilter::Info;
+
+// The log backend: info!, warn! etc. anywhere in the firmware end up in a
+// ring of recent messages in RAM, which the status command prints. There is
+// nothing to print them to at boot, before a terminal has connected.
+struct RingLogger {
+    ring: Mutex<RefCell<LogRing<KEPT_MESSAGES>>>,
+}
+
+static LOGGER: RingLogger = RingLogger {
+    ring: Mutex::new(RefCell::new(LogRing::new())),
+};
+
+impl Log for RingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= MAX_LEVEL
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        cortex_m::interrupt::free(|cs| {
+            // a message logged while the ring is being printed is dropped
+            if let Ok(mut ring) = self.ring.borrow(cs).try_borrow_mut() {
+                ring.push(record.level(), record.args());
+            }
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+// Install the logger. Call it first thing at boot, so no message is lost.
+pub fn init() {
+    // Safety: the RP2040 has no compare-and-swap, so the log crate's safe
+    // setters aren't available. The racy ones are fine as this runs once,
+    // on one core, before anything else logs.
+    unsafe {
+        let _ = log::set_logger_racy(&LOGGER);
+        log::set_max_level_racy(MAX_LEVEL);
+    }
+}
+
+// Run `f` with the messages logged so far, e.g. to print them
+pub fn with_messages<R>(f: impl FnOnce(&LogRing<KEPT_MESSAGES>) -> R) -> R {
+    cortex_m::interrupt::free(|cs| f(&LOGGER.ring.borrow(cs).borrow()))
+}
@@ -3,12 +3,12 @@
 #![no_main]
 #![allow(unused)]
 
-use core::fmt;
+use core::fmt::{self, Write as _};
 
 // HAL traits
 use embedded_hal::blocking::delay::DelayMs;
 use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
-use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::digital::v2::{OutputPin, PinState};
 
 use rp_pico::entry;
 use rp_pico::hal;
@@ -19,53 +19,207 @@ use rp_pico::hal::prelude::*;
 use cortex_m::delay;
 
 // i2c elements
-use rp_pico::hal::fugit::RateExtU32;
+use rp_pico::hal::fugit::{ExtU32, RateExtU32};
 
-// ryu formats a float as a string, as required by the lcd
-use OSU_RPMH::utils::round_to_decimal;
-use ryu; // modularize some no-std math out of main
+// reading_line formats a reading as a string (via ryu), as required by the lcd
+use OSU_RPMH::display::{metric_line, reading_line, Metric, TemperatureUnit};
 
 // custom adapted dht20 driver import
-use dht20::Dht20;
+use OSU_RPMH::data_log::Dump;
+use OSU_RPMH::dht::{self, Dht20};
+use OSU_RPMH::export;
+use OSU_RPMH::scheduler::Scheduler;
+use OSU_RPMH::settings::{Change, Setting, MAX_SAMPLE_INTERVAL};
+use OSU_RPMH::shell::{self, Command, LineEditor};
+use OSU_RPMH::time::{Clock, Duration, Instant};
+
+use log::{debug, info, warn};
 
 // LCD imports
 use liquidcrystal_i2c_rs::{Backlight, Display, Lcd};
-static LCD_ADDRESS: u8 = 0x27;
+
+// How long the reading page and then each derived metric page stay on the
+// LCD. Together they fill the 10 seconds between readings.
+const READING_PAGE_MS: u64 = 4000;
+const METRIC_PAGE_MS: u64 = 1500;
+
+// Intervals of the main loop tasks (the sample and log intervals are
+// settings)
+// blink codes and LED fades; 20 ms is smooth to the eye
+const LED_TICK_MS: u64 = 20;
+const HOUSEKEEPING_MS: u64 = 1000;
+
+// Delay before the first reading, while the sensor powers up and the
+// reset reason is shown on the LCD
+const SENSOR_STARTUP_MS: u64 = 2000;
+
+// The board resets if the main loop doesn't come round within this time
+// (e.g. stuck on a hung I2C transaction). The RP2040 allows up to 8388 ms.
+const WATCHDOG_TIMEOUT_MS: u32 = 2000;
+
+// While dark (see the dark_idle setting) the LEDs only need stepping often
+// enough for the blink codes
+const DARK_LED_TICK_MS: u64 = 100;
+
+// Time given to the USB stack to send the reply to reboot before resetting
+const REBOOT_FLUSH_MS: u64 = 10;
+
+// The jobs run by the main loop scheduler
+#[derive(Clone, Copy, PartialEq)]
+enum Task {
+    SampleSensor,
+    LogReading,
+    RefreshLcd,
+    UpdateLeds,
+    Housekeeping,
+}
 
 use panic_halt as _;
 
-use OSU_RPMH::shared_delay::{self, DelayTimer};
-use OSU_RPMH::pico;
+use OSU_RPMH::shared_delay;
+use OSU_RPMH::blink::{Blinker, FaultCode};
+use OSU_RPMH::leds;
+use OSU_RPMH::logger;
+use rpmh_core::leds::MAX_LEVEL;
+use OSU_RPMH::pico::{self, ResetReason};
 use OSU_RPMH::board;
+use OSU_RPMH::profile;
 
-fn read_sensor<'a, I2C, DELAY, E>(
-    sensor: &mut Dht20<I2C, DELAY>,
-    led_pin_led: &mut impl OutputPin,
-) -> f32
+// Read the sensor and keep its fault codes up to date. On failure the
+// returned description is shown on the LCD in place of the reading. None if
+// it is too soon after the last measurement to take another, in which case
+// that one still stands.
+fn read_sensor<'a, I2C, DELAY, CLOCK, E>(
+    sensor: &mut Dht20<I2C, DELAY, CLOCK, E>,
+    blinker: &mut Blinker,
+) -> Option<Result<dht::Reading, &'static str>>
 where
     I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
     DELAY: DelayMs<u16>,
+    CLOCK: Clock,
     E: fmt::Debug,
 {
-    match sensor.read() {
-        Ok(reading) => {
-            let hum = reading.hum;
-            hum
+    let result = sensor.read();
+    if let Err(dht::Error::ReadTooFast) = result {
+        return None;
+    }
+    let fault = match &result {
+        Ok(reading) if !reading.in_range() => Some(FaultCode::OutOfRange),
+        Ok(_) => None,
+        Err(e) => FaultCode::from_sensor_error(e),
+    };
+    for code in [FaultCode::SensorNack, FaultCode::CrcFailure, FaultCode::OutOfRange] {
+        blinker.set(code, fault == Some(code));
+    }
+
+    Some(match result {
+        Ok(_) if fault.is_some() => Err("Out of range"),
+        Ok(reading) => Ok(reading),
+        Err(e) => Err(e.description()),
+    })
+}
+
+// Step LED fades and the blink codes on the onboard LED (and on the LED bar
+// while there is no reading for it to show)
+fn update_leds(
+    now: Instant,
+    blinker: &mut Blinker,
+    led_pin_led: &mut impl OutputPin,
+    led_array: &mut leds::LedArray,
+    showing_reading: bool,
+) {
+    if showing_reading {
+        led_array.tick(Duration::from_millis(LED_TICK_MS));
+    }
+    match blinker.update(now) {
+        Some(blink) => {
+            let _ = led_pin_led.set_state(PinState::from(blink.lit));
+            if !showing_reading {
+                led_array.indicate(blink.code.index(), blink.lit);
+            }
         }
-        Err(_e) => {
-            let _ = led_pin_led.set_high();
-            101.0 // Return the default value on error
+        None => {
+            let _ = led_pin_led.set_low();
         }
     }
 }
 
 // note: generic parameter I implements the i2c::Write trait, and D implements the DelayMs<u8> trait
-fn print_humidity_to_lcd<I, D>(
+fn print_reading_to_lcd<I, D>(
+    the_lcd: &mut Lcd<I, D>,
+    reading: &dht::Reading,
+    unit: TemperatureUnit,
+    rounding: u32,
+    backlight: Backlight,
+) -> Result<(), <I as embedded_hal::blocking::i2c::Write>::Error>
+where
+    I: embedded_hal::blocking::i2c::Write,
+    D: embedded_hal::blocking::delay::DelayMs<u8>,
+{
+    the_lcd.set_display(Display::On)?;
+    the_lcd.set_backlight(backlight)?;
+
+    the_lcd.clear()?;
+
+    the_lcd.print("Temp & Humidity")?;
+
+    // e.g. "23.4C  45.2%RH"
+    the_lcd.set_cursor_position(0, 1)?;
+    the_lcd.print(reading_line(reading, unit, rounding).as_str())?;
+
+    Ok(())
+}
+
+// Print one derived metric page (dew point, heat index, ...) for a reading
+fn print_metric_to_lcd<I, D>(
     the_lcd: &mut Lcd<I, D>,
-    the_hum: f32,
-    buffer: &mut ryu::Buffer,
+    metric: Metric,
+    reading: &dht::Reading,
+    unit: TemperatureUnit,
     rounding: u32,
 ) -> Result<(), <I as embedded_hal::blocking::i2c::Write>::Error>
+where
+    I: embedded_hal::blocking::i2c::Write,
+    D: embedded_hal::blocking::delay::DelayMs<u8>,
+{
+    the_lcd.clear()?;
+
+    the_lcd.print(metric.title())?;
+
+    the_lcd.set_cursor_position(0, 1)?;
+    the_lcd.print(metric_line(metric, reading, unit, rounding).as_str())?;
+
+    Ok(())
+}
+
+// Show which sensor failure occurred in place of the humidity reading
+fn print_sensor_error_to_lcd<I, D>(
+    the_lcd: &mut Lcd<I, D>,
+    description: &str,
+    backlight: Backlight,
+) -> Result<(), <I as embedded_hal::blocking::i2c::Write>::Error>
+where
+    I: embedded_hal::blocking::i2c::Write,
+    D: embedded_hal::blocking::delay::DelayMs<u8>,
+{
+    the_lcd.set_display(Display::On)?;
+    the_lcd.set_backlight(backlight)?;
+
+    the_lcd.clear()?;
+
+    the_lcd.print("Sensor error")?;
+
+    the_lcd.set_cursor_position(0, 1)?;
+    the_lcd.print(description)?;
+
+    Ok(())
+}
+
+// Show why the board last reset (power on, watchdog, ...) at boot
+fn print_reset_reason_to_lcd<I, D>(
+    the_lcd: &mut Lcd<I, D>,
+    reason: ResetReason,
+) -> Result<(), <I as embedded_hal::blocking::i2c::Write>::Error>
 where
     I: embedded_hal::blocking::i2c::Write,
     D: embedded_hal::blocking::delay::DelayMs<u8>,
@@ -75,11 +229,10 @@ where
 
     the_lcd.clear()?;
 
-    the_lcd.print("Current Humidity")?;
+    the_lcd.print("Reset reason")?;
 
-    the_lcd.set_cursor_position(5, 1)?;
-    the_lcd.print(buffer.format(round_to_decimal(the_hum, rounding)))?;
-    the_lcd.print(" %")?;
+    the_lcd.set_cursor_position(0, 1)?;
+    the_lcd.print(reason.description())?;
 
     Ok(())
 }
@@ -112,52 +265,306 @@ fn main() -> ! {
 
     let mut delays = shared_delay::Delays::new(&rpp_core.shared_timer);
     
+    // The LCD driver keeps a borrow of its bus handle
+    let mut lcd_i2c = rpp_core.i2c.lcd();
+
     // Set up the board and get all components via our struct; partial fix to redeem board components mod struct
     let mut components = board::BoardComponents::setup_board(
         &rpp_core.shared_timer, 
-        rpp_core.sensor_i2c, 
-        &mut rpp_core.i2clcd, 
-        &mut delays.lcd_delay,
+        rpp_core.i2c.sensor(), 
+        &mut lcd_i2c, 
+        &mut delays.board_delay,
         rpp_core.led_pin_led, 
         rpp_core.led_array,
+        rpp_core.addresses,
     );
  
-    // init floating point pass-through variable to copy reading.hum before translating to string to print to LCD
-    let mut the_hum: f32 = 101.0;
+    // Sample interval, LED bands, units, rounding etc. as saved in flash;
+    // can be changed (and saved again) from the serial console
+    let mut settings = rpp_core.settings;
+
+    // Report why we booted, so a watchdog reset doesn't go unnoticed
+    let reset_reason = rpp_core.reset_reason;
+    info!("reset reason: {}", reset_reason.description());
+    let _ = print_reset_reason_to_lcd(&mut components.lcd, reset_reason);
+
+    // Fault codes blinked on the onboard LED (and the LED bar when there is
+    // no reading). A watchdog reset stays flagged until the next power cycle.
+    let timer = &rpp_core.shared_timer;
+    let mut blinker = Blinker::new();
+    blinker.set(FaultCode::WatchdogReset, reset_reason == ResetReason::Watchdog);
+
+    // The main loop runs each of these tasks at its own interval instead of
+    // busy-waiting between steps, so none of them holds up the others
+    let mut scheduler = Scheduler::new(
+        [
+            (Task::SampleSensor, settings.sample_interval),
+            // only logs while logging is on, see the task
+            (Task::LogReading, settings.log_interval.unwrap_or(MAX_SAMPLE_INTERVAL)),
+            (Task::RefreshLcd, Duration::from_millis(READING_PAGE_MS)),
+            (Task::UpdateLeds, Duration::from_millis(LED_TICK_MS)),
+            (Task::Housekeeping, Duration::from_millis(HOUSEKEEPING_MS)),
+        ],
+        timer.now(),
+    );
+    // Give the sensor time to settle after power-up before the first reading
+    scheduler.run_after(Task::SampleSensor, Duration::from_millis(SENSOR_STARTUP_MS), timer.now());
+
+    // The latest reading (or why there isn't one) and the LCD page showing it:
+    // 0 is the reading itself, then one page per derived metric, repeating
+    // (or going dark, see the dark_idle setting) until the next sample
+    let mut latest: Option<Result<dht::Reading, &'static str>> = None;
+    let mut page = 0;
+    let mut dark = false;
+    // When the latest reading was taken, and whether it is in the flash log
+    // yet (each reading is logged at most once, however short the log
+    // interval)
+    let mut latest_at = Instant::from_micros(0);
+    let mut logged = false;
 
-    // Buffer is required by ryu to transform a float into a string
-    let mut buffer = ryu::Buffer::new();
-    // Allows customized rounding. Humidity sensor precision is 6 digits.
-    let rounding: u32 = 1;
+    // Collects the commands typed at the serial console
+    let mut console = LineEditor::new();
+    // A CSV capture starts with a header each time a terminal connects
+    let mut was_connected = false;
+    // A dump of the flash log being sent over the serial port
+    let mut dump: Option<Dump> = None;
+
+    // From here on the loop has to keep coming round to feed the watchdog
+    rpp_core.watchdog.start(WATCHDOG_TIMEOUT_MS.millis());
 
     // To prevent a return from main()
     loop {
-        delays.generic_delay.delay_ms(500);
-
-        // sensor.read will produce two f32 values: reading.hum and reading.temp
-        // parse the sensor reading
-        the_hum = read_sensor(&mut components.sensor, &mut components.led_pin_led);
-        delays.generic_delay.delay_ms(500);
-
-        // Set the LED array to indicate the humidity level
-        components.led_array.update(&the_hum);
-        delays.generic_delay.delay_ms(500);
-
-        // Print the humidity to the LCD
-        if let Err(_) = print_humidity_to_lcd(&mut components.lcd, the_hum, &mut buffer, rounding) {
-            delays.generic_delay.delay_ms(500);
-            
-            // If there is an error printing to the LCD, turn on the onboard LED
-            let _ = components.led_pin_led.set_high();
-            delays.generic_delay.delay_ms(500);
+        rpp_core.watchdog.feed();
+
+        let now = timer.now();
+
+        // Keep the USB serial port responsive to the host, and run any
+        // console commands it has sent
+        let polled = rpp_core.usb_serial.poll();
+        let connected = rpp_core.usb_serial.is_connected();
+        if connected && !was_connected {
+            let _ = export::write_header(&mut rpp_core.usb_serial, settings.format);
+        }
+        was_connected = connected;
+        if polled {
+            let mut received = [0; 64];
+            let count = rpp_core.usb_serial.read(&mut received);
+            for &byte in &received[..count] {
+                let Some(line) = console.push(byte, &mut rpp_core.usb_serial) else {
+                    continue;
+                };
+                let out = &mut rpp_core.usb_serial;
+                let _ = match line.and_then(|line| shell::parse(&line)) {
+                    Ok(None) => Ok(()),
+                    Ok(Some(Command::Help)) => out.write_str(shell::HELP),
+                    Ok(Some(Command::Get(Some(setting)))) => {
+                        let _ = settings.write_setting(out, setting);
+                        out.write_str("\r\n")
+                    }
+                    Ok(Some(Command::Get(None))) => {
+                        for setting in Setting::ALL {
+                            let _ = settings.write_setting(out, setting);
+                            let _ = out.write_str("\r\n");
+                        }
+                        Ok(())
+                    }
+                    Ok(Some(Command::Set(change))) => {
+                        settings.apply(change);
+                        match change {
+                            Change::Interval(interval) => scheduler.set_interval(Task::SampleSensor, interval),
+                            // first log at the new interval from now
+                            Change::LogInterval(Some(interval)) => {
+                                scheduler.set_interval(Task::LogReading, interval);
+                                scheduler.run_after(Task::LogReading, interval, now);
+                            }
+                            // the task checks whether logging is on
+                            Change::LogInterval(None) => {}
+                            Change::Thresholds(thresholds) => components.led_array.set_thresholds(thresholds),
+                            // shown from the next LCD page on
+                            Change::Units(_) | Change::Rounding(_) => {}
+                            Change::Backlight(on) if !dark => {
+                                let backlight = if on { Backlight::On } else { Backlight::Off };
+                                let _ = components.lcd.set_backlight(backlight);
+                            }
+                            // Wake up straight away rather than at the next sample
+                            Change::DarkIdle(false) if dark => {
+                                dark = false;
+                                components.led_array.set_brightness(MAX_LEVEL);
+                                scheduler.set_interval(Task::UpdateLeds, Duration::from_millis(LED_TICK_MS));
+                                page = 0;
+                                scheduler.run_now(Task::RefreshLcd, now);
+                            }
+                            // the LCD address is only looked at during boot;
+                            // going dark waits for the pages to be shown
+                            Change::Backlight(_) | Change::Format(_) | Change::LcdAddress(_) | Change::DarkIdle(_) => {}
+                        }
+                        // Keep it for the next boot
+                        let _ = match rpp_core.settings_store.save(&settings) {
+                            Ok(()) => out.write_str("ok\r\n"),
+                            Err(_) => out.write_str("error: couldn't save to flash, changed until reset\r\n"),
+                        };
+                        // readings in a new format follow its header
+                        match change {
+                            Change::Format(format) => export::write_header(out, format),
+                            _ => Ok(()),
+                        }
+                    }
+                    // As soon as the sensor can take another measurement
+                    Ok(Some(Command::Read)) => {
+                        let wait = components.sensor.next_read_at().map_or(Duration::ZERO, |at| at - now);
+                        scheduler.run_after(Task::SampleSensor, wait, now);
+                        Ok(())
+                    }
+                    // Sent over the next passes of the loop, with the prompt
+                    // after it
+                    Ok(Some(Command::Dump)) => match &rpp_core.data_log {
+                        Ok(data_log) => {
+                            dump = Some(Dump::new(data_log));
+                            continue;
+                        }
+                        Err(e) => write!(out, "error: no data log ({})\r\n", e.description()),
+                    },
+                    Ok(Some(Command::Status)) => logger::with_messages(|log| {
+                        let status = shell::Status {
+                            uptime: now - Instant::from_micros(0),
+                            version: env!("CARGO_PKG_VERSION"),
+                            board: profile::NAME,
+                            reset_reason: reset_reason.description(),
+                            blinker: &blinker,
+                            data_log_fault: rpp_core.data_log.as_ref().err().map(|e| e.description()),
+                            log,
+                        };
+                        shell::write_status(out, &status)
+                    }),
+                    Ok(Some(Command::Reboot)) => {
+                        let _ = out.write_str("rebooting\r\n");
+                        // Give the host a moment to collect the reply
+                        let until = timer.now() + Duration::from_millis(REBOOT_FLUSH_MS);
+                        while timer.now() < until {
+                            out.poll();
+                        }
+                        hal::reset()
+                    }
+                    Err(e) => write!(out, "error: {}\r\n", e.description()),
+                };
+                let _ = rpp_core.usb_serial.write_str(shell::PROMPT);
+            }
         }
 
-        // Dht20 sensor crate class now has a delay function appended to it
-        delays.generic_delay.delay_ms(10000); // sleep 10 seconds between readings
+        // Send the next part of a dump, as much as the host has taken. It is
+        // dropped if the terminal goes away.
+        if let (Some(active), Ok(data_log)) = (&mut dump, &mut rpp_core.data_log) {
+            let usb_serial = &mut rpp_core.usb_serial;
+            let more = connected
+                && active
+                    .send(data_log, |bytes| usb_serial.write_some(bytes))
+                    .unwrap_or(false);
+            if !more {
+                dump = None;
+                let _ = usb_serial.write_str(shell::PROMPT);
+            }
+        }
 
-        // reset LEDs to off
-        components.led_array.clear();
-        delays.generic_delay.delay_ms(500);
+        match scheduler.poll(now) {
+            Some(Task::SampleSensor) => {
+                // sensor.read will produce two f32 values: reading.hum and reading.temp
+                let Some(result) = read_sensor(&mut components.sensor, &mut blinker) else {
+                    continue;
+                };
+                match &result {
+                    // Set the LED array to indicate the humidity level (PWM
+                    // driven LEDs fade to it as the LEDs are updated)
+                    Ok(reading) => components.led_array.update(&reading.hum),
+                    // Blink the fault code on the array rather than show a stale level
+                    Err(_) => components.led_array.clear(),
+                }
+
+                // Stream it to a serial terminal, if one is connected (and
+                // isn't taking a dump, which it would end up in the middle of)
+                let out = &mut rpp_core.usb_serial;
+                let device = &rpp_core.device_id;
+                let _ = match &result {
+                    _ if dump.is_some() => Ok(()),
+                    Ok(reading) => export::write_reading(out, settings.format, device, now, reading, settings.unit, settings.rounding),
+                    Err(description) => export::write_error(out, settings.format, device, now, description),
+                };
+                latest = Some(result);
+                latest_at = now;
+                logged = false;
+
+                // Start the LCD over on the new reading
+                page = 0;
+                scheduler.run_now(Task::RefreshLcd, now);
+                if dark {
+                    dark = false;
+                    components.led_array.set_brightness(MAX_LEVEL);
+                    scheduler.set_interval(Task::UpdateLeds, Duration::from_millis(LED_TICK_MS));
+                }
+            }
+            Some(Task::LogReading) => {
+                // Keep the latest reading (or that there wasn't one) and the
+                // faults raised in the flash log, to be dumped later
+                match (&latest, &mut rpp_core.data_log) {
+                    (Some(result), Ok(data_log)) if settings.log_interval.is_some() && !logged => {
+                        let reading = result.as_ref().ok();
+                        if data_log.append(latest_at, reading, blinker.raised()).is_err() {
+                            warn!("couldn't write to the data log");
+                        }
+                        logged = true;
+                    }
+                    _ => {}
+                }
+            }
+            Some(Task::RefreshLcd) => {
+                // Show the reading, then cycle through the derived metric
+                // pages; a failed read shows which failure occurred instead
+                if settings.dark_idle && page > Metric::ALL.len() {
+                    let _ = components.lcd.set_backlight(Backlight::Off);
+                    let _ = components.lcd.set_display(Display::Off);
+                    components.led_array.set_brightness(0);
+                    scheduler.set_interval(Task::UpdateLeds, Duration::from_millis(DARK_LED_TICK_MS));
+                    // nothing more to show until the next sample wakes the LCD
+                    scheduler.run_after(Task::RefreshLcd, settings.sample_interval, now);
+                    dark = true;
+                    continue;
+                }
+                let backlight = if settings.backlight { Backlight::On } else { Backlight::Off };
+                let (lcd_result, shown_for) = match &latest {
+                    None => continue,
+                    Some(Ok(reading)) if page % (Metric::ALL.len() + 1) == 0 => (
+                        print_reading_to_lcd(&mut components.lcd, reading, settings.unit, settings.rounding, backlight),
+                        READING_PAGE_MS,
+                    ),
+                    Some(Ok(reading)) => (
+                        print_metric_to_lcd(&mut components.lcd, Metric::ALL[page % (Metric::ALL.len() + 1) - 1], reading, settings.unit, settings.rounding),
+                        METRIC_PAGE_MS,
+                    ),
+                    Some(Err(description)) => (
+                        print_sensor_error_to_lcd(&mut components.lcd, description, backlight),
+                        READING_PAGE_MS,
+                    ),
+                };
+                blinker.set(FaultCode::LcdNack, lcd_result.is_err());
+
+                page += 1;
+                scheduler.run_after(Task::RefreshLcd, Duration::from_millis(shown_for), now);
+            }
+            Some(Task::UpdateLeds) => {
+                let showing_reading = matches!(latest, Some(Ok(_)));
+                update_leds(now, &mut blinker, &mut components.led_pin_led, &mut components.led_array, showing_reading);
+            }
+            Some(Task::Housekeeping) => {
+                debug!(
+                    "uptime {} s, faults raised: {}",
+                    now.as_micros() / 1_000_000,
+                    blinker.any_raised()
+                );
+            }
+            // Keep the loop coming round while a dump is being sent
+            None if dump.is_some() => {}
+            // Nothing due: sleep until the next task instead of spinning
+            None => rpp_core.sleeper.sleep_until(now, now + scheduler.time_until_next(now)),
+        }
     }
 }
 // end of file
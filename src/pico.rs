@@ -1,36 +1,236 @@
-use rp_pico::hal::Clock;
 use rp_pico::hal;
 use rp_pico::hal::pac;
+use rp_pico::hal::Clock;
 
 // i2c elements
 use rp_pico::hal::fugit::RateExtU32;
-use rp_pico::hal::gpio::{FunctionI2C, Pin};
+#[cfg(feature = "shared-i2c")]
+use rp_pico::hal::gpio::{FunctionNull, Pin, PullDown};
+
+use embedded_storage::nor_flash::NorFlashErrorKind;
 
+use crate::flash::{self, PicoFlash};
 use crate::leds;
+use crate::logger;
+use crate::profile::{self, BoardPins};
+use rpmh_core::leds::DEFAULT_HYSTERESIS;
+use rpmh_core::settings::Settings;
+use rpmh_core::data_log::{DataLog, OpenError};
+use rpmh_core::settings_store::SettingsStore;
+use rpmh_core::export::DeviceId;
+use rpmh_core::time::Duration;
 use crate::shared_delay::{SharedTimer};
+use crate::sleep::Sleeper;
+use crate::usb_serial::UsbSerial;
+#[cfg(not(feature = "shared-i2c"))]
+use crate::i2c_recovery::LcdBus;
+use crate::i2c_recovery::{RecoveringI2c, SensorBus};
+use rpmh_core::i2c_scan::{self, AddressSet, DEFAULT_LCD_ADDRESS};
+use rpmh_core::shared_i2c::{BusDevice, SharedBus};
+use log::{info, warn};
+
+// How long the PWM driven LEDs take to fade fully on or off between
+// humidity bands
+pub const LED_FADE_TIME: Duration = Duration::from_millis(500);
+
+// Why the RP2040 last came out of reset, from the watchdog's REASON register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetReason {
+    // Power-on, brown-out or the RUN pin (the register is cleared by these)
+    PowerOn,
+    // The watchdog timed out because it wasn't fed in time
+    Watchdog,
+    // The firmware asked for a reset through the watchdog (e.g. a reboot)
+    Forced,
+}
+
+impl ResetReason {
+    fn read(watchdog: &pac::WATCHDOG) -> Self {
+        let reason = watchdog.reason().read();
+        if reason.timer().bit_is_set() {
+            ResetReason::Watchdog
+        } else if reason.force().bit_is_set() {
+            ResetReason::Forced
+        } else {
+            ResetReason::PowerOn
+        }
+    }
+
+    // Short description that fits on one line of the 16x2 LCD
+    pub fn description(&self) -> &'static str {
+        match self {
+            ResetReason::PowerOn => "Power on",
+            ResetReason::Watchdog => "Watchdog reset",
+            ResetReason::Forced => "Forced reset",
+        }
+    }
+}
+
+// The bus each device is on. By default the DHT20 has I2C1 and the LCD has
+// I2C0, on the pins and at the frequencies in the board profile. With the
+// shared-i2c feature both sit on I2C1 at the slower of the two frequencies
+// (the LCD backpack manages 100 kHz), which leaves I2C0 and its pins free
+// for other sensors (see SpareI2c).
+pub type SensorI2c = RecoveringI2c<SensorBus>;
+#[cfg(not(feature = "shared-i2c"))]
+pub type LcdI2c = RecoveringI2c<LcdBus>;
+#[cfg(feature = "shared-i2c")]
+pub type LcdI2c = RecoveringI2c<SensorBus>;
+
+#[cfg(not(feature = "shared-i2c"))]
+const SENSOR_I2C_FREQUENCY_KHZ: u32 = profile::SENSOR_I2C_FREQUENCY_KHZ;
+#[cfg(feature = "shared-i2c")]
+const SENSOR_I2C_FREQUENCY_KHZ: u32 =
+    if profile::LCD_I2C_FREQUENCY_KHZ < profile::SENSOR_I2C_FREQUENCY_KHZ {
+        profile::LCD_I2C_FREQUENCY_KHZ
+    } else {
+        profile::SENSOR_I2C_FREQUENCY_KHZ
+    };
+
+// Names of the buses for messages
+pub const SENSOR_BUS_NAME: &str = "I2C1";
+#[cfg(not(feature = "shared-i2c"))]
+pub const LCD_BUS_NAME: &str = "I2C0";
+#[cfg(feature = "shared-i2c")]
+pub const LCD_BUS_NAME: &str = "I2C1";
+
+// The I2C controller and pins left unused when both devices share I2C1,
+// ready to be set up for another sensor
+#[cfg(feature = "shared-i2c")]
+pub struct SpareI2c {
+    pub block: pac::I2C0,
+    pub sda: Pin<profile::LcdSda, FunctionNull, PullDown>,
+    pub scl: Pin<profile::LcdScl, FunctionNull, PullDown>,
+}
+
+// The I2C buses, each shared between the drivers of the devices on it. Every
+// driver gets its own handle, so the LCD and the DHT20 don't need to know
+// whether they have a bus to themselves.
+pub struct I2cBuses {
+    // recovered automatically if the sensor wedges the bus
+    sensor: SharedBus<SensorI2c>,
+    // recovered automatically, re-initializing the LCD
+    #[cfg(not(feature = "shared-i2c"))]
+    lcd: SharedBus<LcdI2c>,
+}
+
+impl I2cBuses {
+    // Handle for the DHT20 driver
+    pub fn sensor(&self) -> BusDevice<'_, SensorI2c> {
+        self.sensor.device()
+    }
+
+    // Handle for the LCD driver
+    #[cfg(not(feature = "shared-i2c"))]
+    pub fn lcd(&self) -> BusDevice<'_, LcdI2c> {
+        self.lcd.device()
+    }
+
+    // Handle for the LCD driver
+    #[cfg(feature = "shared-i2c")]
+    pub fn lcd(&self) -> BusDevice<'_, LcdI2c> {
+        self.sensor.device()
+    }
+
+    // Scan the buses again, e.g. after plugging a device in
+    pub fn scan(&self) -> BusScan {
+        let sensor_bus = self.sensor.lock(|bus| bus.scan());
+        #[cfg(not(feature = "shared-i2c"))]
+        let lcd_bus = self.lcd.lock(|bus| bus.scan());
+        #[cfg(feature = "shared-i2c")]
+        let lcd_bus = sensor_bus;
+        BusScan { sensor_bus, lcd_bus }
+    }
+}
+
+// Addresses of the devices on the I2C buses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceAddresses {
+    pub lcd: u8,
+    pub sensor: u8,
+}
+
+// Result of scanning the I2C buses (the same set twice when they're shared)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusScan {
+    // The DHT20's bus
+    pub sensor_bus: AddressSet,
+    // The LCD's bus
+    pub lcd_bus: AddressSet,
+}
+
+impl BusScan {
+    // Pick the LCD (PCF8574 or PCF8574A backpack) out of the scan, unless
+    // its address is set (by the saved settings, else the board profile),
+    // and check the DHT20 is there. Anything not found falls back to the
+    // usual address.
+    pub fn addresses(&self, lcd_address: Option<u8>) -> DeviceAddresses {
+        #[allow(unused_mut)]
+        let mut lcd_candidates = self.lcd_bus;
+        // Sharing a bus with the DHT20, a PCF8574A backpack can't use its address
+        #[cfg(feature = "shared-i2c")]
+        lcd_candidates.remove(profile::SENSOR_ADDRESS);
+
+        let lcd = lcd_address
+            .or(profile::LCD_ADDRESS)
+            .or_else(|| i2c_scan::find_lcd(&lcd_candidates))
+            .unwrap_or_else(|| {
+                warn!("no LCD found on {}, trying 0x{:02X}", LCD_BUS_NAME, DEFAULT_LCD_ADDRESS);
+                DEFAULT_LCD_ADDRESS
+            });
+        let sensor = profile::SENSOR_ADDRESS;
+        if !self.sensor_bus.contains(sensor) {
+            warn!("no DHT20 found on {}, trying 0x{:02X}", SENSOR_BUS_NAME, sensor);
+        }
+        DeviceAddresses { lcd, sensor }
+    }
+}
 
 // Abstract the core components from RPP into their own struct
 pub struct CoreComponents {
+    // Why the board last reset (read at boot, before anything else runs)
+    pub reset_reason: ResetReason,
+
+    // Unique to this board (from the flash chip), to tell captures from
+    // several units apart
+    pub device_id: DeviceId,
+
+    // The settings saved in flash (or the defaults), and the store to save
+    // changes to
+    pub settings: Settings,
+    pub settings_store: SettingsStore<PicoFlash>,
+
+    // Readings kept in the flash after the firmware image, for dumping
+    // over the serial console later, or why there is no log to keep them in
+    // (the firmware then runs without one)
+    pub data_log: Result<DataLog<PicoFlash>, OpenError<NorFlashErrorKind>>,
+
+    // Hardware watchdog. It isn't running yet: the firmware starts it with
+    // watchdog.start(timeout) once setup is done and then has to feed() it
+    // more often than that, or the board resets.
+    pub watchdog: hal::Watchdog,
+
     // Shared timer (used for creating separate delays)
     pub shared_timer: SharedTimer,
 
-    // i2c
-    pub sensor_i2c: hal::I2C<
-    pac::I2C1,
-    (
-        Pin<hal::gpio::bank0::Gpio18, FunctionI2C, hal::gpio::PullUp>,
-        Pin<hal::gpio::bank0::Gpio19, FunctionI2C, hal::gpio::PullUp>,
-    ),
-    >,
-
-    // i2c_LCD
-    pub i2clcd: hal::I2C<
-    pac::I2C0,
-    (
-        Pin<hal::gpio::bank0::Gpio0, FunctionI2C, hal::gpio::PullUp>,
-        Pin<hal::gpio::bank0::Gpio1, FunctionI2C, hal::gpio::PullUp>,
-    ),
-    >,
+    // Low-power wait until the next timer alarm (or USB activity)
+    pub sleeper: Sleeper,
+
+    // Serial port over the Pico's USB connector, for streaming readings.
+    // It has to be polled every pass of the main loop.
+    pub usb_serial: UsbSerial,
+
+    // The I2C buses the sensor and LCD are on
+    pub i2c: I2cBuses,
+
+    // I2C0 and the LCD's pins, when the LCD shares the sensor's bus
+    #[cfg(feature = "shared-i2c")]
+    pub spare_i2c: SpareI2c,
+
+    // What answered on the buses at boot, and the device addresses picked
+    // from it
+    pub bus_scan: BusScan,
+    pub addresses: DeviceAddresses,
     // LED Outputs
     // note: we're using PullDown to match what into_push_pull_output()
     //   returns, as we need to explicitly specify all generic type
@@ -40,8 +240,7 @@ pub struct CoreComponents {
     //   definition and the initialization code in setup_board()
     // note: the below lines were added manually from mjanderson's code during merge. todo: remove this comment line once merge is complete
     // On board LED
-    pub led_pin_led:
-        Pin<hal::gpio::bank0::Gpio25, hal::gpio::FunctionSioOutput, hal::gpio::PullDown>,
+    pub led_pin_led: profile::OnboardLed,
 
     // A struct containing all indicator LEDs and methods to control their behavior
     pub led_array: leds::LedArray,
@@ -53,11 +252,49 @@ pub struct CoreComponents {
 impl CoreComponents {
     // Set up all of our board components and return them in a single struct
     pub fn setup_board() -> CoreComponents {
+        // Keep what is logged from here on
+        logger::init();
+
         // This is the Pico-specific setup
         let mut peripherals = pac::Peripherals::take().unwrap();
-        let core = pac::CorePeripherals::take().unwrap();
 
-        // Set up the watchdog driver - needed by the clock setup code
+        // Check why we booted before the watchdog driver takes the peripheral
+        let reset_reason = ResetReason::read(&peripherals.WATCHDOG);
+
+        let device_id = flash::unique_id();
+        info!("Device {}", device_id);
+
+        // Settings saved before the reset, unless the flash holds none that
+        // are intact. Whatever goes wrong, the board comes up on the
+        // defaults rather than not at all.
+        let mut settings_store = SettingsStore::new(PicoFlash, flash::SETTINGS_OFFSET);
+        let settings = match settings_store.load() {
+            Ok(Some(settings)) => settings,
+            Ok(None) => {
+                warn!("no saved settings, using the defaults");
+                Settings::default()
+            }
+            Err(e) => {
+                warn!("couldn't read the saved settings ({:?}), using the defaults", e);
+                Settings::default()
+            }
+        };
+
+        // Carry on with the log left by the previous boots (or start one)
+        let (log_start, log_end) = flash::log_region();
+        let data_log = DataLog::open(PicoFlash, log_start, log_end);
+        match &data_log {
+            Ok(log) => info!(
+                "Data log: {} KB from 0x{:X}, boot {}",
+                (log_end - log_start) / 1024,
+                log_start,
+                log.boot()
+            ),
+            Err(e) => warn!("no data log in 0x{:X}..0x{:X}: {:?}", log_start, log_end, e),
+        }
+
+        // Set up the watchdog driver - needed by the clock setup code, and
+        // kept to supervise the main loop
         let mut watchdog = hal::Watchdog::new(peripherals.WATCHDOG);
 
         // Configure the clocks
@@ -74,9 +311,28 @@ impl CoreComponents {
         .ok()
         .unwrap();
 
+        // The RP2040's 64-bit microsecond timer
+        let mut timer = hal::Timer::new(peripherals.TIMER, &mut peripherals.RESETS, &clocks);
+
+        // One of the timer's alarms wakes the core from sleep when the main
+        // loop has nothing to do
+        let mut core = pac::CorePeripherals::take().unwrap();
+        let mut sleeper = Sleeper::new(timer.alarm_0().unwrap(), &mut core.SCB);
+
         // This shared timer allows us to create separate delays that all wrap
         // around the same timer inside the pico       
-        let shared_timer = SharedTimer::new(core.SYST, clocks.system_clock.freq().to_Hz());
+        let shared_timer = SharedTimer::new(timer);
+
+        // USB serial port, clocked by the USB PLL. USB traffic wakes the core
+        // so the port can be serviced straight away.
+        let usb_serial = UsbSerial::new(
+            peripherals.USBCTRL_REGS,
+            peripherals.USBCTRL_DPRAM,
+            clocks.usb_clock,
+            &mut peripherals.RESETS,
+            timer,
+        );
+        sleeper.wake_on(pac::Interrupt::USBCTRL_IRQ);
 
         // The single-cycle I/O block controls our GPIO pins
         let sio = hal::Sio::new(peripherals.SIO);
@@ -89,50 +345,105 @@ impl CoreComponents {
             &mut peripherals.RESETS,
         );
 
+        // Split the pins up by what they're wired to on this board revision
+        let board_pins = BoardPins::take(pins, peripherals.PWM, &mut peripherals.RESETS);
+        info!("Board {}", profile::NAME);
+
         // Set the onboard RPP LED to be an output
-        let led_pin_led = pins.led.into_push_pull_output();
-
-        // Initialize an led array with five led pins
-        let led_array = leds::LedArray::new(
-            pins.gpio12,
-            pins.gpio13,
-            pins.gpio14,
-            pins.gpio15,
-            pins.gpio16,
+        let led_pin_led = board_pins.onboard_led.into_push_pull_output();
+
+        // Initialize an led array with the five led outputs (bottom of the
+        // bar first), using the saved humidity bands (evenly spaced unless
+        // changed, see LedThresholds for presets) and the default hysteresis
+        // margin
+        #[allow(unused_mut)]
+        let mut led_array = leds::LedArray::new(
+            board_pins.leds,
+            settings.thresholds,
+            DEFAULT_HYSTERESIS,
         );
+        #[cfg(feature = "pwm-leds")]
+        led_array.set_fade_time(LED_FADE_TIME);
 
         // Configure two pins as being I²C, not GPIO
-        let sda_sensor_pin = pins.gpio18.reconfigure();
-        let scl_sensor_pin = pins.gpio19.reconfigure();
+        let sda_sensor_pin = board_pins.sensor_sda.reconfigure();
+        let scl_sensor_pin = board_pins.sensor_scl.reconfigure();
 
         // init for embedded hal I2C
         let sensor_i2c = hal::I2C::i2c1(
             peripherals.I2C1,
             sda_sensor_pin,
             scl_sensor_pin,
-            400.kHz(),
+            SENSOR_I2C_FREQUENCY_KHZ.kHz(),
             &mut peripherals.RESETS,
             &clocks.system_clock,
         );
+        let sensor_i2c = RecoveringI2c::new(
+            sensor_i2c,
+            SENSOR_I2C_FREQUENCY_KHZ.kHz(),
+            clocks.system_clock.freq(),
+        );
 
         // Configure two pins as being I²C for LCD SDA/SCL
-        let sda_lcd_pin = pins.gpio0.reconfigure(); 
-        let scl_lcd_pin = pins.gpio1.reconfigure(); 
-
-        let i2clcd = hal::I2C::i2c0( // removed mut
-            peripherals.I2C0,
-            sda_lcd_pin,
-            scl_lcd_pin,
-            100.kHz(),
-            &mut peripherals.RESETS,
-            &clocks.system_clock,
+        #[cfg(not(feature = "shared-i2c"))]
+        let i2c = {
+            let sda_lcd_pin = board_pins.lcd_sda.reconfigure();
+            let scl_lcd_pin = board_pins.lcd_scl.reconfigure();
+
+            let i2clcd = hal::I2C::i2c0(
+                peripherals.I2C0,
+                sda_lcd_pin,
+                scl_lcd_pin,
+                profile::LCD_I2C_FREQUENCY_KHZ.kHz(),
+                &mut peripherals.RESETS,
+                &clocks.system_clock,
+            );
+            let i2clcd = RecoveringI2c::new(
+                i2clcd,
+                profile::LCD_I2C_FREQUENCY_KHZ.kHz(),
+                clocks.system_clock.freq(),
+            );
+
+            I2cBuses {
+                sensor: SharedBus::new(sensor_i2c),
+                lcd: SharedBus::new(i2clcd),
+            }
+        };
+
+        // ... or leave them alone, the LCD being on the sensor's bus
+        #[cfg(feature = "shared-i2c")]
+        let (i2c, spare_i2c) = (
+            I2cBuses {
+                sensor: SharedBus::new(sensor_i2c),
+            },
+            SpareI2c {
+                block: peripherals.I2C0,
+                sda: board_pins.lcd_sda,
+                scl: board_pins.lcd_scl,
+            },
         );
 
+        // Find the LCD and sensor instead of assuming their addresses
+        let bus_scan = i2c.scan();
+        let addresses = bus_scan.addresses(settings.lcd_address);
+        info!("LCD at 0x{:02X}, DHT20 at 0x{:02X}", addresses.lcd, addresses.sensor);
+
         // Return all components in the form of the struct (LCD will need to be added here as well)
         CoreComponents {
+            reset_reason,
+            device_id,
+            settings,
+            settings_store,
+            data_log,
+            watchdog,
             shared_timer,
-            sensor_i2c,
-            i2clcd,
+            sleeper,
+            usb_serial,
+            i2c,
+            #[cfg(feature = "shared-i2c")]
+            spare_i2c,
+            bus_scan,
+            addresses,
             led_pin_led,
             led_array,
         }
@@ -0,0 +1,122 @@
+use rp_pico::hal::gpio::{bank0, FunctionNull, FunctionSioOutput, Pin, PullDown};
+use rp_pico::hal::pac;
+
+use crate::leds::LedOutput;
+#[cfg(feature = "pwm-leds")]
+use crate::leds::LedChannel;
+#[cfg(feature = "pwm-leds")]
+use rpmh_core::leds::PwmLed;
+
+// Everything that differs between our PCB revisions: which GPIO each part is
+// wired to, how fast the I2C buses run and where the devices answer.
+// CoreComponents::setup_board builds the board from the profile compiled in.
+// There is only rev 1 so far; a new revision gets its own board_profile!
+// below, picked with a cargo feature.
+//
+// Each pin is given by its rp_pico::Pins field and its hal type (which
+// can't be derived from the field name in a macro). The controllers are
+// fixed, the sensor on I2C1 and the LCD on I2C0, and the compiler rejects
+// pins those controllers can't use; likewise the PWM channel listed for an
+// LED has to be the one its GPIO is wired to in the RP2040. LEDs go from the
+// bottom of the bar (red) to the top (red2). An LCD address of None means
+// it is found by scanning its bus.
+macro_rules! board_profile {
+    (
+        name: $name:literal,
+        onboard_led: $led_pin:ident / $led_id:ident,
+        sensor_i2c: {
+            sda: $sensor_sda:ident / $sensor_sda_id:ident,
+            scl: $sensor_scl:ident / $sensor_scl_id:ident,
+            frequency_khz: $sensor_khz:literal $(,)?
+        },
+        lcd_i2c: {
+            sda: $lcd_sda:ident / $lcd_sda_id:ident,
+            scl: $lcd_scl:ident / $lcd_scl_id:ident,
+            frequency_khz: $lcd_khz:literal $(,)?
+        },
+        leds: [$($led:ident => $slice:ident . $channel:ident),* $(,)?],
+        lcd_address: $lcd_address:expr,
+        sensor_address: $sensor_address:expr $(,)?
+    ) => {
+        pub const NAME: &str = $name;
+
+        pub type OnboardLed = Pin<bank0::$led_id, FunctionSioOutput, PullDown>;
+        pub type SensorSda = bank0::$sensor_sda_id;
+        pub type SensorScl = bank0::$sensor_scl_id;
+        pub type LcdSda = bank0::$lcd_sda_id;
+        pub type LcdScl = bank0::$lcd_scl_id;
+
+        pub const SENSOR_I2C_FREQUENCY_KHZ: u32 = $sensor_khz;
+        pub const LCD_I2C_FREQUENCY_KHZ: u32 = $lcd_khz;
+
+        pub const LCD_ADDRESS: Option<u8> = $lcd_address;
+        pub const SENSOR_ADDRESS: u8 = $sensor_address;
+
+        // The board's pins, split out of rp_pico::Pins by what they're for
+        pub struct BoardPins {
+            pub onboard_led: Pin<bank0::$led_id, FunctionNull, PullDown>,
+            pub sensor_sda: Pin<SensorSda, FunctionNull, PullDown>,
+            pub sensor_scl: Pin<SensorScl, FunctionNull, PullDown>,
+            pub lcd_sda: Pin<LcdSda, FunctionNull, PullDown>,
+            pub lcd_scl: Pin<LcdScl, FunctionNull, PullDown>,
+            // The LED bar, already set up as outputs
+            pub leds: [LedOutput; 5],
+        }
+
+        impl BoardPins {
+            // Take the pins, setting the LEDs up as PWM channels (with the
+            // pwm-leds feature) or plain GPIO outputs
+            #[allow(unused_variables)]
+            pub fn take(pins: rp_pico::Pins, pwm: pac::PWM, resets: &mut pac::RESETS) -> Self {
+                #[cfg(feature = "pwm-leds")]
+                let leds = {
+                    // The default 125 MHz / 65536 (~1.9 kHz) is well above
+                    // visible flicker
+                    let mut slices = rp_pico::hal::pwm::Slices::new(pwm, resets);
+                    $(slices.$slice.enable();)*
+                    [$({
+                        let mut channel = slices.$slice.$channel;
+                        channel.output_to(pins.$led);
+                        PwmLed::new(LedChannel::from(channel))
+                    }),*]
+                };
+                #[cfg(not(feature = "pwm-leds"))]
+                let leds = [$(pins.$led.into_push_pull_output().into_dyn_pin()),*];
+
+                BoardPins {
+                    onboard_led: pins.$led_pin,
+                    sensor_sda: pins.$sensor_sda,
+                    sensor_scl: pins.$sensor_scl,
+                    lcd_sda: pins.$lcd_sda,
+                    lcd_scl: pins.$lcd_scl,
+                    leds,
+                }
+            }
+        }
+    };
+}
+
+// Rev 1, the original build (and the wiring described in the README)
+board_profile! {
+    name: "Rev 1",
+    onboard_led: led / Gpio25,
+    sensor_i2c: {
+        sda: gpio18 / Gpio18,
+        scl: gpio19 / Gpio19,
+        frequency_khz: 400,
+    },
+    lcd_i2c: {
+        sda: gpio0 / Gpio0,
+        scl: gpio1 / Gpio1,
+        frequency_khz: 100,
+    },
+    leds: [
+        gpio15 => pwm7.channel_b, // red
+        gpio14 => pwm7.channel_a, // yellow
+        gpio16 => pwm0.channel_a, // green
+        gpio13 => pwm6.channel_b, // yellow2
+        gpio12 => pwm6.channel_a, // red2
+    ],
+    lcd_address: None,
+    sensor_address: 0x38,
+}
@@ -1,39 +1,47 @@
-use cortex_m::peripheral::SYST;
-use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+use rp_pico::hal;
+use rpmh_core::time::{Clock, Duration, Instant};
 
 // SharedTimer is an abstraction of the internal Pico clock that can be used
-// to create reusable DelayTimers
+// to create reusable DelayTimers. It is backed by the RP2040's 64-bit
+// microsecond TIMER peripheral, which keeps counting (without wrapping) for
+// the life of the device.
 pub struct SharedTimer {
-    systick_freq_hz: u32,
-    systick: SYST,
+    timer: hal::Timer,
 }
 
 impl SharedTimer {
-    pub fn new(mut systick: SYST, core_freq_hz: u32) -> Self {
-        // Configure SysTick to count down from its max value continuously
-        systick.set_reload(u32::MAX);
-        systick.clear_current();
-        systick.enable_counter();
-
-        Self {
-            systick_freq_hz: core_freq_hz,
-            systick,
-        }
+    pub fn new(timer: hal::Timer) -> Self {
+        Self { timer }
     }
 
-    pub fn now(&self) -> u32 {
-        // SysTick counts down, so we invert it for elapsed time comparison
-        u32::MAX - SYST::get_current()
+    // Current time since boot
+    pub fn now(&self) -> Instant {
+        Instant::from_micros(self.timer.get_counter().ticks())
     }
 
-    pub fn release(self) -> SYST {
-        self.systick
+    // Time passed since an earlier call to now()
+    pub fn elapsed(&self, since: Instant) -> Duration {
+        self.now().duration_since(since)
+    }
+
+    pub fn release(self) -> hal::Timer {
+        self.timer
+    }
+}
+
+impl Clock for SharedTimer {
+    fn now(&self) -> Instant {
+        SharedTimer::now(self)
     }
 }
 
 // DelayTimer is a Delay-like implementation that wraps the Pico clock.
 // You can create multiple DelayTimer instances from the same SharedTimer without
-// issue (and therefore avoid shared delay issues)
+// issue (and therefore avoid shared delay issues). It only holds a reference
+// to the SharedTimer, so copies of one DelayTimer can be handed to several
+// drivers at once
+#[derive(Clone, Copy)]
 pub struct DelayTimer<'a> {
     timer: &'a SharedTimer,
 }
@@ -42,20 +50,47 @@ impl<'a> DelayTimer<'a> {
     pub fn new(timer: &'a SharedTimer) -> Self {
         Self { timer }
     }
-}
 
-// Several different implementations for delay_ms to allow operation
-// with varying interfaces from the different crates we are using
-impl<T> DelayMs<T> for DelayTimer<'_> 
-where 
-    T: TryInto<u32>,
-    T::Error: core::fmt::Debug,
-{
-    fn delay_ms(&mut self, ms: T) {
-        let ms: u32 = ms.try_into().unwrap();
-        let ticks = ms * (self.timer.systick_freq_hz / 100000);
+    // Busy-wait until the given amount of time has passed
+    pub fn wait(&self, duration: Duration) {
         let start = self.timer.now();
-        while self.timer.now().wrapping_sub(start) < ticks {}
+        while self.timer.elapsed(start) < duration {}
+    }
+}
+
+// Several different implementations for delay_ms/delay_us (u8, u16 and u32)
+// to allow operation with varying interfaces from the different crates we are using
+macro_rules! impl_delays {
+    ($($t:ty),*) => {
+        $(
+            impl DelayMs<$t> for DelayTimer<'_> {
+                fn delay_ms(&mut self, ms: $t) {
+                    self.wait(Duration::from_millis(ms as u64));
+                }
+            }
+
+            impl DelayUs<$t> for DelayTimer<'_> {
+                fn delay_us(&mut self, us: $t) {
+                    self.wait(Duration::from_micros(us as u64));
+                }
+            }
+        )*
+    };
+}
+
+impl_delays!(u8, u16, u32);
+
+// i32 is what an untyped literal like delay_ms(500) defaults to, so it is
+// supported too (negative values don't wait at all)
+impl DelayMs<i32> for DelayTimer<'_> {
+    fn delay_ms(&mut self, ms: i32) {
+        self.wait(Duration::from_millis(ms.max(0) as u64));
+    }
+}
+
+impl DelayUs<i32> for DelayTimer<'_> {
+    fn delay_us(&mut self, us: i32) {
+        self.wait(Duration::from_micros(us.max(0) as u64));
     }
 }
 
@@ -63,17 +98,18 @@ where
 // rpp_core and components structs in main. This Delays struct creates
 // a convenient data structure for accessing them
 pub struct Delays<'a> {
-    pub lcd_delay: DelayTimer<'a>,
+    // shared by the board's drivers (DHT20 sensor and LCD)
+    pub board_delay: DelayTimer<'a>,
     pub generic_delay: DelayTimer<'a>,
 }
 
 impl<'a> Delays<'a> {
     pub fn new(shared_timer: &'a SharedTimer) -> Self {
-        let lcd_delay = DelayTimer::new(shared_timer);
+        let board_delay = DelayTimer::new(shared_timer);
         let generic_delay = DelayTimer::new(shared_timer);
 
         return Delays {
-            lcd_delay,
+            board_delay,
             generic_delay,
         }
     }
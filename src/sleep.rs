@@ -0,0 +1,88 @@
+use cortex_m::peripheral::{NVIC, SCB};
+use rp_pico::hal;
+use rp_pico::hal::pac;
+use rp_pico::hal::timer::{Alarm, Alarm0};
+use rpmh_core::time::Instant;
+
+// Waits shorter than this aren't worth sleeping for (scheduling the alarm
+// takes a few microseconds itself), so they return straight away
+const MIN_SLEEP_MICROS: u64 = 50;
+
+// How many interrupts can be registered with wake_on()
+const MAX_WAKE_INTERRUPTS: usize = 2;
+
+// Sleeper puts the core to sleep until a TIMER alarm fires, instead of
+// spinning at 125 MHz like DelayTimer does. The clocks keep running while
+// asleep, so the shared timer (and with it the scheduler) keeps counting.
+//
+// The alarm's interrupt is never enabled in the NVIC: with SEVONPEND set,
+// the interrupt going pending is enough to wake the core from WFE, so no
+// interrupt handler is needed and nothing else in the firmware changes.
+// Peripherals that need servicing as soon as they have something to say
+// (USB) can be registered with wake_on() to end the sleep early the same way.
+//
+// Dormant mode isn't used: it stops the TIMER along with every other clock,
+// and the Pico has no 32 kHz crystal to keep the RTC running to wake it.
+pub struct Sleeper {
+    alarm: Alarm0,
+    // interrupts that end a sleep early
+    wake_interrupts: [Option<pac::Interrupt>; MAX_WAKE_INTERRUPTS],
+}
+
+impl Sleeper {
+    pub fn new(mut alarm: Alarm0, scb: &mut SCB) -> Self {
+        scb.set_sevonpend();
+        alarm.enable_interrupt();
+        Self {
+            alarm,
+            wake_interrupts: [None; MAX_WAKE_INTERRUPTS],
+        }
+    }
+
+    // Also wake up when `interrupt` goes pending. Panics if more than
+    // MAX_WAKE_INTERRUPTS are registered.
+    pub fn wake_on(&mut self, interrupt: pac::Interrupt) {
+        let slot = self.wake_interrupts.iter_mut().find(|slot| slot.is_none()).unwrap();
+        *slot = Some(interrupt);
+    }
+
+    fn wake_interrupts(&self) -> impl Iterator<Item = pac::Interrupt> + '_ {
+        self.wake_interrupts.iter().flatten().copied()
+    }
+
+    // Sleep until `wake_at` (a time from SharedTimer::now()) or one of the
+    // wake_on() interrupts, or return straight away if it is too close or
+    // already past
+    pub fn sleep_until(&mut self, now: Instant, wake_at: Instant) {
+        if wake_at.duration_since(now).as_micros() < MIN_SLEEP_MICROS {
+            return;
+        }
+
+        // Start from a clean slate: an alarm left over from a sleep that
+        // ended early, or an interrupt that was already serviced, would
+        // otherwise stay pending and never signal again. A peripheral that
+        // still needs servicing pends its interrupt again right away.
+        self.alarm.clear_interrupt();
+        NVIC::unpend(pac::Interrupt::TIMER_IRQ_0);
+        for interrupt in self.wake_interrupts() {
+            NVIC::unpend(interrupt);
+        }
+
+        let timestamp = hal::timer::Instant::from_ticks(wake_at.as_micros());
+        if self.alarm.schedule_at(timestamp).is_err() {
+            return;
+        }
+
+        // Any other event can wake us too, so go back to sleep until the
+        // alarm has actually fired
+        while !self.alarm.finished() {
+            if self.wake_interrupts().any(NVIC::is_pending) {
+                return;
+            }
+            cortex_m::asm::wfe();
+        }
+
+        self.alarm.clear_interrupt();
+        NVIC::unpend(pac::Interrupt::TIMER_IRQ_0);
+    }
+}
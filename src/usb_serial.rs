@@ -0,0 +1,137 @@
+use core::fmt;
+
+use rp_pico::hal;
+use rp_pico::hal::pac;
+use rp_pico::hal::usb::UsbBus;
+use usb_device::class_prelude::UsbBusAllocator;
+use usb_device::prelude::*;
+use usbd_serial::SerialPort;
+
+// pid.codes test VID/PID for CDC-ACM devices (the one used by the rp-pico
+// examples); any serial terminal picks it up without a driver
+const VID_PID: UsbVidPid = UsbVidPid(0x16c0, 0x27dd);
+
+// How long a write waits for the host to make room in the port's buffer
+// before giving up on it. The host collects a 64-byte packet every frame
+// (1 ms) while a terminal is reading, so this is only reached when it isn't.
+const STALL_TIMEOUT_MS: u64 = 20;
+
+// The Pico's USB port as a CDC-ACM serial port (/dev/ttyACM0, COMx), running
+// off the 48 MHz USB PLL set up by init_clocks_and_plls.
+//
+// The USB stack has to be serviced with poll() whenever the host talks to
+// it. Its interrupt isn't enabled in the NVIC, but like the timer alarm it
+// wakes the core from Sleeper's WFE when it goes pending, so polling once
+// per main loop pass is enough.
+pub struct UsbSerial {
+    device: UsbDevice<'static, UsbBus>,
+    port: SerialPort<'static, UsbBus>,
+    timer: hal::Timer,
+    // Set when a write gave up waiting on the host, so the writes after it
+    // don't each wait again; cleared once the host takes data again
+    stalled: bool,
+    // Whether the bus was serviced with something for the port while a
+    // write was waiting, for the next poll() to report
+    polled_while_writing: bool,
+}
+
+impl UsbSerial {
+    pub fn new(
+        regs: pac::USBCTRL_REGS,
+        dpram: pac::USBCTRL_DPRAM,
+        usb_clock: hal::clocks::UsbClock,
+        resets: &mut pac::RESETS,
+        timer: hal::Timer,
+    ) -> Self {
+        // The device and the port both borrow the bus allocator for as long
+        // as the firmware runs, so it lives in a static
+        let bus: &'static UsbBusAllocator<UsbBus> = cortex_m::singleton!(
+            : UsbBusAllocator<UsbBus> =
+                UsbBusAllocator::new(UsbBus::new(regs, dpram, usb_clock, true, resets))
+        )
+        .unwrap();
+
+        let port = SerialPort::new(bus);
+        let device = UsbDeviceBuilder::new(bus, VID_PID)
+            .strings(&[StringDescriptors::default()
+                .manufacturer("OSU-RPMH")
+                .product("RPMH humidity monitor")
+                .serial_number("RPMH")])
+            .unwrap()
+            .device_class(usbd_serial::USB_CLASS_CDC)
+            .build();
+
+        Self {
+            device,
+            port,
+            timer,
+            stalled: false,
+            polled_while_writing: false,
+        }
+    }
+
+    // Service the USB bus. Returns true if the port may have data to read.
+    pub fn poll(&mut self) -> bool {
+        let polled = self.device.poll(&mut [&mut self.port]);
+        polled || core::mem::take(&mut self.polled_while_writing)
+    }
+
+    // Copy out what the host has sent, returning how many bytes were read
+    // (0 when there is nothing waiting)
+    pub fn read(&mut self, buffer: &mut [u8]) -> usize {
+        self.port.read(buffer).unwrap_or(0)
+    }
+
+    // Queue as much of `bytes` as the port has room for, returning how much
+    // that was (0 while it is full, or no terminal is connected). Unlike
+    // write_str, the caller can send the rest later instead of losing it.
+    pub fn write_some(&mut self, bytes: &[u8]) -> usize {
+        if !self.is_connected() {
+            return 0;
+        }
+        self.port.write(bytes).unwrap_or(0)
+    }
+
+    // Whether a terminal has the port open (it raises DTR when it does)
+    pub fn is_connected(&self) -> bool {
+        self.device.state() == UsbDeviceState::Configured && self.port.dtr()
+    }
+}
+
+// Text written to the port is queued for the host. Nothing is sent while no
+// terminal is connected. Text longer than the port's buffer (help, status)
+// is sent as the host takes it, servicing the bus meanwhile; if the host
+// stops taking it for STALL_TIMEOUT_MS the rest is dropped rather than
+// holding up the main loop.
+impl fmt::Write for UsbSerial {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if !self.is_connected() {
+            return Ok(());
+        }
+
+        let mut bytes = s.as_bytes();
+        let mut waiting_since = self.timer.get_counter();
+        while !bytes.is_empty() {
+            match self.port.write(bytes) {
+                Ok(written) => {
+                    bytes = &bytes[written..];
+                    waiting_since = self.timer.get_counter();
+                    self.stalled = false;
+                }
+                Err(UsbError::WouldBlock)
+                    if !self.stalled && (self.timer.get_counter() - waiting_since).to_millis() < STALL_TIMEOUT_MS =>
+                {
+                    if self.device.poll(&mut [&mut self.port]) {
+                        self.polled_while_writing = true;
+                    }
+                }
+                Err(UsbError::WouldBlock) => {
+                    self.stalled = true;
+                    break;
+                }
+                Err(_) => break,
+            }
+        }
+        Ok(())
+    }
+}